@@ -0,0 +1,96 @@
+//! Rebuilding the aggregated book from every venue book versus applying the levels that changed
+//! in one venue's update, each followed by building the grouped levels that are published. Run
+//! with `cargo bench`.
+
+#[allow(dead_code, unused_imports)]
+#[path = "../src/book.rs"]
+mod book;
+
+use agg_ws::client::Exchange;
+use book::{apply_delta, changed_prices, AggBook, Levels};
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion};
+use rust_decimal::Decimal;
+
+const VENUES: [Exchange; 3] = [Exchange::Kraken, Exchange::Gdax, Exchange::Hyperliquid];
+// Levels whose size moves in a typical update
+const CHANGED: usize = 10;
+// Levels published per side
+const DEPTH: usize = 50;
+
+// Bids below and asks above 10_000 in 0.01 steps, offset per venue so prices partly overlap
+fn venue_book(venue: usize, depth: usize) -> (Levels, Levels) {
+    let tick = Decimal::new(1, 2);
+    let mid = Decimal::from(10_000);
+    let size = Decimal::new(15 + venue as i64, 1);
+    let bids = (1..=depth)
+        .map(|i| (mid - tick * Decimal::from(i + venue), size))
+        .collect();
+    let asks = (1..=depth)
+        .map(|i| (mid + tick * Decimal::from(i + venue), size))
+        .collect();
+    (bids, asks)
+}
+
+// The same book with sizes changed near the top, where most updates land
+fn update(levels: &Levels) -> Levels {
+    let mut next = levels.clone();
+    for size in next.values_mut().take(CHANGED) {
+        *size += Decimal::ONE;
+    }
+    next
+}
+
+fn agg_book(c: &mut Criterion) {
+    let mut group = c.benchmark_group("agg_book");
+    let grouping = Decimal::new(5, 2);
+    for depth in [100, 1_000, 10_000] {
+        let books: Vec<(Levels, Levels)> =
+            (0..VENUES.len()).map(|v| venue_book(v, depth)).collect();
+        let next = (update(&books[0].0), update(&books[0].1));
+        let mut after = books.clone();
+        after[0] = next.clone();
+
+        group.bench_with_input(BenchmarkId::new("rebuild", depth), &after, |b, after| {
+            b.iter(|| {
+                let mut agg = AggBook::new();
+                for (exchange, (bids, asks)) in VENUES.iter().zip(after.iter()) {
+                    agg.merge(*exchange, bids, asks);
+                }
+                agg.grouped(grouping, DEPTH)
+            })
+        });
+
+        let mut initial = AggBook::new();
+        for (exchange, (bids, asks)) in VENUES.iter().zip(books.iter()) {
+            initial.merge(*exchange, bids, asks);
+        }
+        let bids: Vec<(Exchange, &Levels)> = VENUES
+            .iter()
+            .zip(after.iter())
+            .map(|(e, b)| (*e, &b.0))
+            .collect();
+        let asks: Vec<(Exchange, &Levels)> = VENUES
+            .iter()
+            .zip(after.iter())
+            .map(|(e, b)| (*e, &b.1))
+            .collect();
+        group.bench_with_input(BenchmarkId::new("delta", depth), &initial, |b, initial| {
+            b.iter_batched(
+                || initial.clone(),
+                |mut agg| {
+                    let changed_bids = changed_prices(&books[0].0, &next.0);
+                    let changed_asks = changed_prices(&books[0].1, &next.1);
+                    apply_delta(&mut agg.bids, &changed_bids, &bids);
+                    apply_delta(&mut agg.asks, &changed_asks, &asks);
+                    let view = agg.grouped(grouping, DEPTH);
+                    (agg, view)
+                },
+                BatchSize::LargeInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, agg_book);
+criterion_main!(benches);
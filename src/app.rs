@@ -1,420 +1,385 @@
-use std::collections::{HashMap, HashSet, VecDeque};
+use std::{collections::HashMap, sync::Arc};
 
-use agg_ws::{
-    book::Book,
-    client::{AsyncClient, Channel, ChannelType, ClientResp, ClientRespMsg, Exchange},
-    trades::Trade,
-};
 use crossterm::event::{KeyCode, KeyEvent};
 use ratatui::widgets::ListState;
+use rust_decimal::Decimal;
+use tokio::sync::mpsc;
 
-use crate::{AggBook, AggExchange, Level, Result};
+use crate::{
+    config::{AssetConfig, Config},
+    engine::{Command, Snapshot},
+    replay::{self, ReplayControl},
+    sweep::SweepOrder,
+    symbols::Instrument,
+    AggExchange, Result,
+};
 
+/// UI state. Market data is owned by the engine task and arrives as snapshots.
 pub struct App {
     pub screens: Vec<AppFocus>,
     pub screen_idx: usize,
+    /// Rows the tape and book panes are scrolled past the latest trade and the inside spread
+    pub tape_scroll: usize,
+    pub book_scroll: usize,
+    /// Rows of trades and book levels that fit on screen, updated on each draw
+    pub tape_rows: usize,
+    pub book_rows: usize,
     pub assets: Vec<String>,
     pub asset_idx: usize,
-    pub exchanges: HashMap<usize, Vec<AggExchange>>,
-    pub exchange_state: HashMap<usize, ListState>,
-    pub trades: Vec<Trade>,
-    pub book: AggBook,
-    pub client: AsyncClient,
-    pub subscriptions: HashMap<usize, HashSet<Channel>>,
-    pub tickers: HashMap<usize, HashMap<Exchange, String>>, // TODO: Load from db or config
-    pub sub_queue: HashSet<Channel>,
-    pub tapes: HashMap<Channel, VecDeque<Trade>>,
-    pub books: HashMap<Channel, Book>,
-    pub dp: HashMap<usize, u32>,
+    pub exchanges: HashMap<String, Vec<AggExchange>>,
+    pub exchange_state: HashMap<String, ListState>,
+    pub instruments: HashMap<String, Instrument>,
+    pub dp: HashMap<String, u32>,
+    /// Book price groupings of each asset and the index of the one in use, ungrouped if None
+    pub groupings: HashMap<String, Vec<Decimal>>,
+    pub grouping: HashMap<String, Option<usize>>,
+    pub prompt: Option<Prompt>,
+    pub show_subs: bool,
+    /// Latest snapshot published by the engine
+    pub snapshot: Arc<Snapshot>,
+    pub commands: mpsc::UnboundedSender<Command>,
 }
 
+#[derive(Debug, PartialEq, Eq)]
 pub enum AppFocus {
     AssetTab,
     Exchange,
+    Trades,
+    Book,
+}
+
+/// Single line text input, e.g. `AVAX 2 kraken coinbase` to add an asset
+#[derive(Debug, Default)]
+pub struct Prompt {
+    pub kind: PromptKind,
+    pub input: String,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub enum PromptKind {
+    #[default]
+    AddAsset,
+    /// Order to price against the book, e.g. `buy 50`
+    Sweep,
+    /// Replay position to jump to, e.g. `14:30` or `-5m`
+    Jump,
 }
 
 impl App {
-    pub fn new() -> Result<App> {
-        let mut default_list_state = ListState::default();
-        default_list_state.select(Some(0));
-        Ok(App {
-            screens: vec![AppFocus::AssetTab, AppFocus::Exchange],
+    pub fn new(config: &Config, commands: mpsc::UnboundedSender<Command>) -> Result<App> {
+        let mut app = App {
+            screens: vec![
+                AppFocus::AssetTab,
+                AppFocus::Exchange,
+                AppFocus::Trades,
+                AppFocus::Book,
+            ],
             screen_idx: 0,
-            assets: vec!["BTC".to_string(), "ETH".to_string(), "SOL".to_string()],
-            asset_idx: 2,
-            exchanges: HashMap::from([
-                (
-                    0,
-                    vec![
-                        AggExchange::Aggregate,
-                        AggExchange::Exchange(Exchange::Kraken),
-                        AggExchange::Exchange(Exchange::Gdax),
-                        AggExchange::Exchange(Exchange::Hyperliquid),
-                    ],
-                ),
-                (
-                    1,
-                    vec![
-                        AggExchange::Aggregate,
-                        AggExchange::Exchange(Exchange::Kraken),
-                        AggExchange::Exchange(Exchange::Gdax),
-                        AggExchange::Exchange(Exchange::Hyperliquid),
-                    ],
-                ),
-                (
-                    2,
-                    vec![
-                        AggExchange::Aggregate,
-                        AggExchange::Exchange(Exchange::Kraken),
-                        AggExchange::Exchange(Exchange::Gdax),
-                        AggExchange::Exchange(Exchange::Hyperliquid),
-                    ],
-                ),
-            ]),
-            exchange_state: HashMap::from([
-                (0, default_list_state.clone()),
-                (1, default_list_state.clone()),
-                (2, default_list_state.clone()),
-                (3, default_list_state.clone()),
-            ]),
-            trades: Vec::with_capacity(50),
-            book: AggBook::new(),
-            client: AsyncClient::new(),
-            subscriptions: HashMap::new(),
-            tickers: HashMap::from([
-                (
-                    0,
-                    HashMap::from([
-                        (Exchange::Gdax, "BTC-USD".to_string()),
-                        (Exchange::Kraken, "XBT/USD".to_string()),
-                        (Exchange::Hyperliquid, "BTC".to_string()),
-                    ]),
-                ),
-                (
-                    1,
-                    HashMap::from([
-                        (Exchange::Gdax, "ETH-USD".to_string()),
-                        (Exchange::Kraken, "ETH/USD".to_string()),
-                        (Exchange::Hyperliquid, "ETH".to_string()),
-                    ]),
-                ),
-                (
-                    2,
-                    HashMap::from([
-                        (Exchange::Gdax, "SOL-USD".to_string()),
-                        (Exchange::Kraken, "SOL/USD".to_string()),
-                        (Exchange::Hyperliquid, "SOL".to_string()),
-                    ]),
-                ),
-            ]),
-            sub_queue: HashSet::new(),
-            tapes: HashMap::new(),
-            books: HashMap::new(),
-            dp: HashMap::from([(0, 8), (1, 8), (2, 3)]),
-        })
+            tape_scroll: 0,
+            book_scroll: 0,
+            tape_rows: 0,
+            book_rows: 0,
+            assets: Vec::with_capacity(config.assets.len()),
+            asset_idx: config.default_asset,
+            exchanges: HashMap::new(),
+            exchange_state: HashMap::new(),
+            instruments: HashMap::new(),
+            dp: HashMap::new(),
+            groupings: HashMap::new(),
+            grouping: HashMap::new(),
+            prompt: None,
+            show_subs: false,
+            snapshot: Arc::new(Snapshot::default()),
+            commands,
+        };
+        for asset in config.assets.iter() {
+            app.insert_asset(asset);
+        }
+        Ok(app)
     }
 
-    pub fn response_handler(&mut self, resp_msg: ClientRespMsg) {
-        tracing::info!("Response handler {:?}", resp_msg.channel);
-        match resp_msg.resp {
-            ClientResp::Subscribed => self.handle_subscribed(resp_msg.channel),
-            ClientResp::Unsubscribed => {}
-            ClientResp::Tape(t) => self.handle_tape(resp_msg.channel, t),
-            ClientResp::Book(b) => self.handle_book(resp_msg.channel, b),
-            ClientResp::Last(_) => {}
-        }
+    // Name of the selected asset, used as the key for all per asset state
+    pub fn asset(&self) -> &str {
+        &self.assets[self.asset_idx]
     }
 
-    pub fn handle_subscribed(&mut self, channel: Channel) {
-        self.subscriptions
-            .entry(self.asset_idx)
-            .and_modify(|hs| {
-                hs.insert(channel.clone());
-            })
-            .or_insert_with(|| HashSet::from([channel.clone()]));
+    // The engine only stops once the UI is gone, so sends do not fail while the app is running
+    fn send(&self, cmd: Command) {
+        let _ = self.commands.send(cmd);
     }
 
-    pub fn handle_tape(&mut self, channel: Channel, tape: VecDeque<Trade>) {
-        tracing::info!("Tape: {:?}", tape);
-        self.tapes.insert(channel, tape);
+    // Tell the engine which asset and exchange to aggregate and publish. A new view starts at the
+    // latest trade and the inside spread.
+    fn send_view(&mut self) {
+        self.tape_scroll = 0;
+        self.book_scroll = 0;
+        let asset = self.asset().to_string();
+        let exchange = self
+            .exchange_state
+            .get(&asset)
+            .unwrap()
+            .selected()
+            .map(|i| self.exchanges.get(&asset).unwrap()[i].clone());
+        let grouping = self.grouping();
+        self.send(Command::View {
+            asset,
+            exchange,
+            grouping,
+        });
     }
 
-    pub fn handle_book(&mut self, channel: Channel, book: Book) {
-        self.books.insert(channel, book);
+    /// Price increment the selected asset's book is grouped by.
+    pub fn grouping(&self) -> Option<Decimal> {
+        let asset = self.asset();
+        self.grouping
+            .get(asset)
+            .unwrap()
+            .map(|i| self.groupings.get(asset).unwrap()[i])
     }
 
-    fn get_channels(&self) -> Vec<Channel> {
-        let mut channels = Vec::new();
-        // Get trade channels
-        for ticker in self.tickers.get(&self.asset_idx).unwrap().iter() {
-            let channel = Channel {
-                exchange: *ticker.0,
-                channel: ChannelType::Tape,
-                market: ticker.1.clone(),
+    // Step to the next coarser grouping, from ungrouped to the finest
+    pub fn coarser_grouping(&mut self) {
+        let asset = self.asset().to_string();
+        let n = self.groupings.get(&asset).unwrap().len();
+        self.grouping.entry(asset).and_modify(|g| {
+            *g = match *g {
+                Some(i) if i + 1 < n => Some(i + 1),
+                Some(i) => Some(i),
+                None => Some(0),
             };
-            channels.push(channel);
-            let channel = Channel {
-                exchange: *ticker.0,
-                channel: ChannelType::Book,
-                market: ticker.1.clone(),
+        });
+        self.send_view();
+    }
+
+    // Step to the next finer grouping, ungrouped after the finest
+    pub fn finer_grouping(&mut self) {
+        let asset = self.asset().to_string();
+        self.grouping.entry(asset).and_modify(|g| {
+            *g = match *g {
+                Some(0) | None => None,
+                Some(i) => Some(i - 1),
             };
-            channels.push(channel);
-        }
-        channels
+        });
+        self.send_view();
     }
 
-    pub async fn manage_state(&mut self) {
-        if !self.sub_queue.is_empty() {
-            self.subscribe_channel().await;
-        }
-        self.poll_data().await;
-        // tokio::time::sleep(Duration::from_millis(250)).await;
+    fn insert_asset(&mut self, asset: &AssetConfig) {
+        let mut default_list_state = ListState::default();
+        default_list_state.select(Some(0));
+        let mut venues = vec![AggExchange::Aggregate];
+        venues.extend(asset.venues.iter().map(|ex| AggExchange::Exchange(*ex)));
+        self.assets.push(asset.name.clone());
+        self.exchanges.insert(asset.name.clone(), venues);
+        self.exchange_state
+            .insert(asset.name.clone(), default_list_state);
+        self.instruments
+            .insert(asset.name.clone(), asset.instrument.clone());
+        self.dp.insert(asset.name.clone(), asset.size_dp);
+        self.groupings
+            .insert(asset.name.clone(), asset.groupings.clone());
+        self.grouping.insert(asset.name.clone(), asset.grouping);
     }
 
-    async fn subscribe_channel(&mut self) {
-        if let Some(channel) = self.sub_queue.iter().next().cloned() {
-            self.sub_queue.remove(&channel);
-            tracing::info!("Subscribing to {:?}", channel);
-            match self.client.start_and_subscribe(channel.clone()).await {
-                Ok(resp) => {
-                    tracing::info!("Sub req resp: {:?}", resp);
-                }
-                Err(e) => tracing::error!("Sub error: {:?}", e),
-            }
+    pub fn add_asset(&mut self, asset: &AssetConfig) -> Result<()> {
+        if self.assets.contains(&asset.name) {
+            return Err(format!("asset {} is already open", asset.name).into());
         }
+        self.insert_asset(asset);
+        self.send(Command::AddAsset(asset.clone()));
+        self.select_asset(self.assets.len() - 1);
+        Ok(())
     }
 
-    pub async fn queue_subs(&mut self) {
-        // Get channels and send to client to subscirbe
-        let channels = self.get_channels();
-        tracing::debug!("Channels: {:?}", channels);
-        for channel in channels.iter() {
-            // Skip if already subscribed to channel
-            if let Some(subs) = self.subscriptions.get(&self.asset_idx) {
-                if subs.contains(channel) {
-                    tracing::debug!("Channel {:?} already subbed.", channel);
-                    continue;
-                };
-            }
-            tracing::info!("Inserting channel into sub queue: {:?}", channel);
-            self.sub_queue.insert(channel.clone());
+    pub fn remove_asset(&mut self) {
+        // Always keep one asset open
+        if self.assets.len() == 1 {
+            return;
         }
+        let asset = self.asset().to_string();
+        self.assets.remove(self.asset_idx);
+        self.exchanges.remove(&asset);
+        self.exchange_state.remove(&asset);
+        self.instruments.remove(&asset);
+        self.dp.remove(&asset);
+        self.groupings.remove(&asset);
+        self.grouping.remove(&asset);
+        if self.asset_idx >= self.assets.len() {
+            self.asset_idx = self.assets.len() - 1;
+        }
+        self.send_view();
+        self.send(Command::RemoveAsset(asset));
     }
 
-    // Poll data for asset and aggregate if needed
-    pub async fn poll_data(&mut self) {
-        if let Some(exchange_idx) = self.exchange_state.get(&self.asset_idx).unwrap().selected() {
-            // There is an exchange selected, poll trades and insert into app data storage
-            if exchange_idx == 0 {
-                let channels = self.get_channels();
-                tracing::info!("Polling all tapes and books.");
-                for channel in channels.iter() {
-                    match channel.channel {
-                        ChannelType::Tape => {
-                            self.client.get_tape(channel.clone()).await.unwrap();
-                        }
-                        ChannelType::Book => {
-                            self.client.get_book(channel.clone()).await.unwrap();
-                        }
-                    };
-                }
-            } else {
-                let exchange = self.exchanges.get(&self.asset_idx).unwrap()[exchange_idx].clone();
-                if let AggExchange::Exchange(ex) = exchange {
-                    let ticker = self.tickers.get(&self.asset_idx).unwrap().get(&ex).unwrap();
-                    let channel = Channel {
-                        exchange: ex,
-                        channel: ChannelType::Tape,
-                        market: ticker.clone(),
-                    };
-                    tracing::info!("Polling {:?} - {:?} tape and book.", ex, ticker);
-                    self.client.get_tape(channel.clone()).await.unwrap();
-                    // Get Book for Exchange
-                    let channel = Channel {
-                        exchange: ex,
-                        channel: ChannelType::Book,
-                        market: ticker.clone(),
-                    };
-                    self.client.get_book(channel.clone()).await.unwrap();
-                }
-            }
-        }
+    pub fn select_asset(&mut self, idx: usize) {
+        self.asset_idx = idx;
+        self.send_view();
     }
 
-    // Update the app Book and Trades state based on the selected Asset and Exchange
-    pub fn update_state(&mut self) {
-        self.trades = Vec::with_capacity(50);
-        self.book = AggBook::new();
-        if let Some(exchange_idx) = self.exchange_state.get(&self.asset_idx).unwrap().selected() {
-            // Aggregated Exchange is selected - Merge all tapes and book for Asset
-            if exchange_idx == 0 {
-                let channels = self.get_channels();
-                self.update_state_agg_trades(&channels);
-                // Get all books and aggregate them into one
-                self.update_state_agg_book(&channels);
-            } else {
-                // Copy the book and tape for the given Exchange and Asset
-                let exchange = self.exchanges.get(&self.asset_idx).unwrap()[exchange_idx].clone();
-                if let AggExchange::Exchange(ex) = exchange {
-                    let ticker = self
-                        .tickers
-                        .get(&self.asset_idx)
-                        .unwrap()
-                        .get(&ex)
-                        .unwrap()
-                        .clone();
-                    // Copy the tape
-                    self.update_state_trades(&ex, &ticker);
-                    // Get book for exchange and convert into AggBook structure
-                    self.update_state_book(&ex, &ticker);
-                }
+    // Tab cycles the focused pane and the paging keys scroll the tape or book when focused.
+    // Returns false to quit
+    pub fn handle_key_press(&mut self, key_event: &KeyEvent) -> bool {
+        if self.prompt.is_some() {
+            self.handle_prompt_key(key_event);
+            return true;
+        }
+        match key_event.code {
+            KeyCode::Char('q') => {
+                return false;
             }
+            KeyCode::Char('a') => self.prompt = Some(Prompt::default()),
+            KeyCode::Char('c') => self.toggle_sweep(),
+            KeyCode::Char(' ') if self.replaying() => self.replay(ReplayControl::TogglePause),
+            KeyCode::Char('+' | '=') if self.replaying() => self.replay(ReplayControl::Faster),
+            KeyCode::Char('-') if self.replaying() => self.replay(ReplayControl::Slower),
+            KeyCode::Char('n') if self.replaying() => self.replay(ReplayControl::Step),
+            KeyCode::Char('j') if self.replaying() => {
+                self.prompt = Some(Prompt {
+                    kind: PromptKind::Jump,
+                    ..Prompt::default()
+                })
+            }
+            KeyCode::Char('x') => self.remove_asset(),
+            KeyCode::Char('r') => self.send(Command::Resubscribe),
+            KeyCode::Char('s') => self.show_subs = !self.show_subs,
+            KeyCode::Char(']') => self.coarser_grouping(),
+            KeyCode::Char('[') => self.finer_grouping(),
+            KeyCode::Right => self.next_asset(),
+            KeyCode::Left => self.prev_asset(),
+            KeyCode::Down => self.next_exchange(),
+            KeyCode::Up => self.prev_exchange(),
+            KeyCode::Enter => self.unselect(),
+            KeyCode::Tab => self.next_focus(),
+            KeyCode::PageDown => self.scroll_down(),
+            KeyCode::PageUp => self.scroll_up(),
+            KeyCode::Home => self.scroll_home(),
+            _ => (),
         }
+        true
     }
 
-    pub fn update_state_agg_trades(&mut self, channels: &[Channel]) {
-        let mut trades: Vec<Trade> = Vec::with_capacity(100 * channels.len());
-        for channel in channels.iter() {
-            if let Some(trades_vd) = self.tapes.get(channel).cloned() {
-                trades.append(&mut trades_vd.into());
+    fn handle_prompt_key(&mut self, key_event: &KeyEvent) {
+        let prompt = self.prompt.as_mut().unwrap();
+        match key_event.code {
+            KeyCode::Esc => self.prompt = None,
+            KeyCode::Backspace => {
+                prompt.input.pop();
             }
+            KeyCode::Char(c) => prompt.input.push(c),
+            KeyCode::Enter => {
+                let input = prompt.input.clone();
+                let result = match prompt.kind {
+                    PromptKind::AddAsset => match AssetConfig::parse_spec(&input) {
+                        Ok(asset) => self.add_asset(&asset),
+                        Err(e) => Err(e.into()),
+                    },
+                    PromptKind::Sweep => {
+                        let instrument = self.instruments.get(self.asset()).unwrap();
+                        match SweepOrder::parse(&input, instrument) {
+                            Ok(order) => {
+                                self.send(Command::Sweep(Some(order)));
+                                Ok(())
+                            }
+                            Err(e) => Err(e.into()),
+                        }
+                    }
+                    PromptKind::Jump => match &self.snapshot.replay {
+                        Some(status) => match replay::parse_jump(&input, status) {
+                            Ok(t) => {
+                                self.replay(ReplayControl::JumpTo(t));
+                                Ok(())
+                            }
+                            Err(e) => Err(e.into()),
+                        },
+                        None => Ok(()),
+                    },
+                };
+                match result {
+                    Ok(()) => self.prompt = None,
+                    Err(e) => self.prompt.as_mut().unwrap().error = Some(e.to_string()),
+                }
+            }
+            _ => (),
         }
-        trades.sort_by_key(|t| t.dt);
-        self.trades = trades.into_iter().rev().take(25).collect();
     }
 
-    pub fn update_state_trades(&mut self, exchange: &Exchange, ticker: &str) {
-        let channel = Channel {
-            exchange: *exchange,
-            channel: ChannelType::Tape,
-            market: ticker.to_string(),
-        };
-        if let Some(trades_vd) = self.tapes.get(&channel).cloned() {
-            let trades_v: Vec<Trade> = trades_vd.into();
-            self.trades = trades_v.into_iter().rev().take(25).collect();
+    // Ask for an order to price against the book, or close the one being shown
+    pub fn toggle_sweep(&mut self) {
+        if self.snapshot.sweep.is_some() {
+            self.send(Command::Sweep(None));
         } else {
-            self.trades = Vec::with_capacity(50);
+            self.prompt = Some(Prompt {
+                kind: PromptKind::Sweep,
+                ..Prompt::default()
+            });
         }
     }
 
-    pub fn update_state_agg_book(&mut self, channels: &[Channel]) {
-        let mut agg_book = AggBook::new();
-        for channel in channels.iter() {
-            if let Some(book) = self.books.get(channel).cloned() {
-                self.merge_exchange_book(&mut agg_book, &book, channel.exchange);
-            }
-        }
-        self.book = agg_book;
+    pub fn replaying(&self) -> bool {
+        self.snapshot.replay.is_some()
     }
 
-    pub fn update_state_book(&mut self, exchange: &Exchange, ticker: &str) {
-        let channel = Channel {
-            exchange: *exchange,
-            channel: ChannelType::Book,
-            market: ticker.to_string(),
-        };
-        if let Some(book) = self.books.get(&channel).cloned() {
-            self.map_exchange_book(channel.exchange, book);
-        } else {
-            self.book = AggBook::new();
-        }
+    fn replay(&self, control: ReplayControl) {
+        self.send(Command::Replay(control));
     }
 
-    pub fn map_exchange_book(&mut self, exchange: Exchange, book: Book) {
-        let mut agg_book = AggBook::new();
-        agg_book.asks.extend(book.asks.iter().map(|l| {
-            (
-                *l.0,
-                Level {
-                    size: *l.1,
-                    exchange: AggExchange::Exchange(exchange),
-                },
-            )
-        }));
-        agg_book.bids.extend(book.bids.iter().map(|l| {
-            (
-                *l.0,
-                Level {
-                    size: *l.1,
-                    exchange: AggExchange::Exchange(exchange),
-                },
-            )
-        }));
-        self.book = agg_book;
+    pub fn next_focus(&mut self) {
+        self.screen_idx = (self.screen_idx + 1) % self.screens.len();
     }
 
-    pub fn merge_exchange_book(&self, agg_book: &mut AggBook, book: &Book, exchange: Exchange) {
-        // Merge asks
-        for ask in book.asks.iter() {
-            agg_book
-                .asks
-                .entry(*ask.0)
-                .and_modify(|l| {
-                    l.size += ask.1;
-                    l.exchange = AggExchange::Aggregate;
-                })
-                .or_insert_with(|| Level {
-                    size: *ask.1,
-                    exchange: AggExchange::Exchange(exchange),
-                });
-        }
-        // Merge bids
-        for bid in book.bids.iter() {
-            agg_book
-                .bids
-                .entry(*bid.0)
-                .and_modify(|l| {
-                    l.size += bid.1;
-                    l.exchange = AggExchange::Aggregate;
-                })
-                .or_insert_with(|| Level {
-                    size: *bid.1,
-                    exchange: AggExchange::Exchange(exchange),
-                });
+    pub fn focus(&self) -> &AppFocus {
+        &self.screens[self.screen_idx]
+    }
+
+    // Page the focused pane away from the latest trade or inside spread. Scrolling past the last
+    // row is clamped when drawn.
+    pub fn scroll_down(&mut self) {
+        match self.focus() {
+            AppFocus::Trades => self.tape_scroll += self.tape_rows.max(1),
+            AppFocus::Book => self.book_scroll += self.book_rows.max(1),
+            _ => (),
         }
     }
 
-    // Screen focus is not currently implemented. Key event is structure to change key functionality
-    // based on what screen is selected
-    pub async fn handle_key_press(&mut self, key_event: &KeyEvent) -> bool {
-        match key_event.code {
-            KeyCode::Char('q') => {
-                return false;
+    pub fn scroll_up(&mut self) {
+        match self.focus() {
+            AppFocus::Trades => {
+                self.tape_scroll = self.tape_scroll.saturating_sub(self.tape_rows.max(1))
+            }
+            AppFocus::Book => {
+                self.book_scroll = self.book_scroll.saturating_sub(self.book_rows.max(1))
             }
-            KeyCode::Right => self.next_asset().await,
-            KeyCode::Left => self.prev_asset().await,
-            KeyCode::Down => self.next_exchange(),
-            KeyCode::Up => self.prev_exchange(),
-            KeyCode::Enter => self.unselect(),
-            KeyCode::Tab => self.next_focus(),
             _ => (),
         }
-        true
     }
 
-    pub fn next_focus(&mut self) {
-        self.screen_idx = (self.screen_idx + 1) % self.screens.len();
+    pub fn scroll_home(&mut self) {
+        match self.focus() {
+            AppFocus::Trades => self.tape_scroll = 0,
+            AppFocus::Book => self.book_scroll = 0,
+            _ => (),
+        }
     }
 
-    pub async fn next_asset(&mut self) {
-        self.asset_idx = (self.asset_idx + 1) % self.assets.len();
-        self.queue_subs().await;
+    pub fn next_asset(&mut self) {
+        self.select_asset((self.asset_idx + 1) % self.assets.len());
     }
 
-    pub async fn prev_asset(&mut self) {
+    pub fn prev_asset(&mut self) {
         if self.asset_idx > 0 {
-            self.asset_idx -= 1;
+            self.select_asset(self.asset_idx - 1);
         } else {
-            self.asset_idx = self.assets.len() - 1;
+            self.select_asset(self.assets.len() - 1);
         }
-        self.queue_subs().await;
     }
 
     pub fn next_exchange(&mut self) {
-        self.exchange_state.entry(self.asset_idx).and_modify(|ls| {
+        let asset = self.asset().to_string();
+        let n = self.exchanges.get(&asset).unwrap().len();
+        self.exchange_state.entry(asset).and_modify(|ls| {
             let i = match ls.selected() {
                 Some(i) => {
-                    if i >= self.exchanges.get(&self.asset_idx).unwrap().len() - 1 {
+                    if i >= n - 1 {
                         0
                     } else {
                         i + 1
@@ -424,14 +389,17 @@ impl App {
             };
             ls.select(Some(i));
         });
+        self.send_view();
     }
 
     pub fn prev_exchange(&mut self) {
-        self.exchange_state.entry(self.asset_idx).and_modify(|ls| {
+        let asset = self.asset().to_string();
+        let n = self.exchanges.get(&asset).unwrap().len();
+        self.exchange_state.entry(asset).and_modify(|ls| {
             let i = match ls.selected() {
                 Some(i) => {
                     if i == 0 {
-                        self.exchanges.get(&self.asset_idx).unwrap().len() - 1
+                        n - 1
                     } else {
                         i - 1
                     }
@@ -440,19 +408,15 @@ impl App {
             };
             ls.select(Some(i));
         });
+        self.send_view();
     }
 
     pub fn unselect(&mut self) {
-        self.exchange_state.entry(self.asset_idx).and_modify(|ls| {
-            ls.select(None);
-        });
-    }
-
-    pub async fn _last_message(&mut self) {
-        for channel in self.get_channels() {
-            tracing::info!("Getting last message for {:?}", channel);
-            let last_message = self.client.get_last(channel).await;
-            tracing::info!("Last message: {:?}", last_message);
-        }
+        self.exchange_state
+            .entry(self.asset().to_string())
+            .and_modify(|ls| {
+                ls.select(None);
+            });
+        self.send_view();
     }
 }
@@ -0,0 +1,306 @@
+use std::{
+    collections::HashMap,
+    fs::{File, OpenOptions},
+    io::{self, Write},
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use agg_ws::client::Exchange;
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
+
+use crate::{book::Levels, AggExchange};
+
+/// One venue's bid above another venue's ask.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cross {
+    pub asset: String,
+    /// Venue to sell on and its best bid
+    pub bid: (Exchange, Decimal),
+    /// Venue to buy on and its best ask
+    pub ask: (Exchange, Decimal),
+    /// Size that can be bought on the ask venue and sold on the bid venue at a gross profit
+    pub size: Decimal,
+    /// Top of book edge relative to the mid, in basis points
+    pub edge_bps: Decimal,
+    /// Edge after both venues' taker fees, if any fees are configured
+    pub net_bps: Option<Decimal>,
+}
+
+impl Cross {
+    pub fn describe(&self) -> String {
+        let mut s = format!(
+            "{} bid {} > {} ask {}, size {}, edge {} bps",
+            AggExchange::Exchange(self.bid.0).as_display(),
+            self.bid.1,
+            AggExchange::Exchange(self.ask.0).as_display(),
+            self.ask.1,
+            self.size.normalize(),
+            self.edge_bps.round_dp(1)
+        );
+        if let Some(net) = self.net_bps {
+            s.push_str(&format!(", net {} bps", net.round_dp(1)));
+        }
+        s
+    }
+}
+
+/// Every pair of venues where one's bid is above the other's ask. `venues` are the bids and asks
+/// of each venue's book, `fees_bps` the taker fee of each venue.
+pub fn detect(
+    asset: &str,
+    venues: &[(Exchange, &Levels, &Levels)],
+    fees_bps: &HashMap<Exchange, Decimal>,
+) -> Vec<Cross> {
+    let mut crosses = Vec::new();
+    for (bid_ex, bids, _) in venues.iter() {
+        for (ask_ex, _, asks) in venues.iter() {
+            if bid_ex == ask_ex {
+                continue;
+            }
+            let (Some((bid, _)), Some((ask, _))) = (bids.iter().next_back(), asks.iter().next())
+            else {
+                continue;
+            };
+            if bid <= ask {
+                continue;
+            }
+            let mid = (bid + ask) / Decimal::TWO;
+            let edge_bps = (bid - ask) / mid * Decimal::from(10_000);
+            let net_bps = if fees_bps.is_empty() {
+                None
+            } else {
+                let fee = |ex: &Exchange| fees_bps.get(ex).copied().unwrap_or_default();
+                Some(edge_bps - fee(bid_ex) - fee(ask_ex))
+            };
+            crosses.push(Cross {
+                asset: asset.to_string(),
+                bid: (*bid_ex, *bid),
+                ask: (*ask_ex, *ask),
+                size: crossable_size(bids, asks),
+                edge_bps,
+                net_bps,
+            });
+        }
+    }
+    crosses
+}
+
+// Size matched walking down the bids and up the asks while the bid is above the ask
+fn crossable_size(bids: &Levels, asks: &Levels) -> Decimal {
+    let mut bids = bids.iter().rev().map(|(p, s)| (*p, *s));
+    let mut asks = asks.iter().map(|(p, s)| (*p, *s));
+    let mut bid = bids.next();
+    let mut ask = asks.next();
+    let mut size = Decimal::ZERO;
+    while let (Some((bid_px, bid_sz)), Some((ask_px, ask_sz))) = (bid, ask) {
+        if bid_px <= ask_px {
+            break;
+        }
+        let fill = bid_sz.min(ask_sz);
+        size += fill;
+        bid = if bid_sz > fill {
+            Some((bid_px, bid_sz - fill))
+        } else {
+            bids.next()
+        };
+        ask = if ask_sz > fill {
+            Some((ask_px, ask_sz - fill))
+        } else {
+            asks.next()
+        };
+    }
+    size
+}
+
+// A cross that is still open, with the best seen over its lifetime
+#[derive(Debug)]
+struct OpenCross {
+    started: DateTime<Utc>,
+    since: Instant,
+    max_size: Decimal,
+    max_edge_bps: Decimal,
+    max_net_bps: Option<Decimal>,
+}
+
+/// Tracks crosses from one check to the next, writing a line to the log file when each closes.
+#[derive(Debug, Default)]
+pub struct CrossLog {
+    file: Option<File>,
+    open: HashMap<(String, Exchange, Exchange), OpenCross>,
+}
+
+impl CrossLog {
+    /// Append closed crosses to the file at `path`.
+    pub fn open(&mut self, path: &Path) -> io::Result<()> {
+        self.file = Some(OpenOptions::new().create(true).append(true).open(path)?);
+        Ok(())
+    }
+
+    /// Record the crosses found across every asset at `now`, closing any no longer crossed.
+    pub fn update(&mut self, crosses: &[Cross], now: Instant) {
+        for cross in crosses.iter() {
+            let key = (cross.asset.clone(), cross.bid.0, cross.ask.0);
+            let open = self.open.entry(key).or_insert_with(|| {
+                tracing::info!("Crossed market: {} {}", cross.asset, cross.describe());
+                OpenCross {
+                    started: Utc::now(),
+                    since: now,
+                    max_size: cross.size,
+                    max_edge_bps: cross.edge_bps,
+                    max_net_bps: cross.net_bps,
+                }
+            });
+            open.max_size = open.max_size.max(cross.size);
+            open.max_edge_bps = open.max_edge_bps.max(cross.edge_bps);
+            open.max_net_bps = open.max_net_bps.max(cross.net_bps);
+        }
+        let closed: Vec<(String, Exchange, Exchange)> = self
+            .open
+            .keys()
+            .filter(|k| {
+                !crosses
+                    .iter()
+                    .any(|c| c.asset == k.0 && c.bid.0 == k.1 && c.ask.0 == k.2)
+            })
+            .cloned()
+            .collect();
+        for key in closed {
+            let open = self.open.remove(&key).unwrap();
+            self.write(&key, &open, now.duration_since(open.since));
+        }
+    }
+
+    /// Close and log every cross still open at `now`, on shutdown.
+    pub fn close(&mut self, now: Instant) {
+        for (key, open) in std::mem::take(&mut self.open) {
+            self.write(&key, &open, now.duration_since(open.since));
+        }
+    }
+
+    /// Forget open crosses without logging them.
+    pub fn clear(&mut self) {
+        self.open.clear();
+    }
+
+    /// How long the cross between two venues has been open.
+    pub fn duration(&self, cross: &Cross, now: Instant) -> Duration {
+        self.open
+            .get(&(cross.asset.clone(), cross.bid.0, cross.ask.0))
+            .map(|o| now.duration_since(o.since))
+            .unwrap_or_default()
+    }
+
+    fn write(&mut self, key: &(String, Exchange, Exchange), open: &OpenCross, duration: Duration) {
+        let Some(file) = self.file.as_mut() else {
+            return;
+        };
+        let net = open
+            .max_net_bps
+            .map(|n| format!(" max_net_bps={}", n.round_dp(1)))
+            .unwrap_or_default();
+        let line = format!(
+            "{} asset={} bid={} ask={} max_size={} max_edge_bps={}{} duration_ms={}\n",
+            open.started.to_rfc3339(),
+            key.0,
+            AggExchange::Exchange(key.1).as_display(),
+            AggExchange::Exchange(key.2).as_display(),
+            open.max_size.normalize(),
+            open.max_edge_bps.round_dp(1),
+            net,
+            duration.as_millis()
+        );
+        if let Err(e) = file.write_all(line.as_bytes()) {
+            tracing::error!("Failed to write cross log: {:?}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    fn levels(levels: &[(Decimal, Decimal)]) -> Levels {
+        levels.iter().cloned().collect()
+    }
+
+    #[test]
+    pub fn detects_crosses() {
+        let kraken_bids = levels(&[
+            (dec!(101), dec!(1)),
+            (dec!(100.5), dec!(2)),
+            (dec!(99), dec!(5)),
+        ]);
+        let kraken_asks = levels(&[(dec!(102), dec!(1))]);
+        let coinbase_bids = levels(&[(dec!(99.5), dec!(1))]);
+        let coinbase_asks = levels(&[
+            (dec!(100), dec!(1.5)),
+            (dec!(100.6), dec!(3)),
+            (dec!(103), dec!(1)),
+        ]);
+        let venues = [
+            (Exchange::Kraken, &kraken_bids, &kraken_asks),
+            (Exchange::Gdax, &coinbase_bids, &coinbase_asks),
+        ];
+        let crosses = detect("BTC", &venues, &HashMap::new());
+        assert_eq!(crosses.len(), 1);
+        let cross = &crosses[0];
+        assert_eq!(cross.bid, (Exchange::Kraken, dec!(101)));
+        assert_eq!(cross.ask, (Exchange::Gdax, dec!(100)));
+        // 1 at 101 against 100, then 0.5 at 100.5 against 100, then 100.5 is below 100.6
+        assert_eq!(cross.size, dec!(1.5));
+        assert_eq!(cross.edge_bps.round_dp(2), dec!(99.50));
+        assert!(cross.net_bps.is_none());
+
+        let fees = [(Exchange::Kraken, dec!(26)), (Exchange::Gdax, dec!(60))]
+            .into_iter()
+            .collect();
+        let crosses = detect("BTC", &venues, &fees);
+        assert_eq!(crosses[0].net_bps.unwrap().round_dp(2), dec!(13.50));
+    }
+
+    #[test]
+    pub fn logs_closed_crosses() {
+        let path =
+            std::env::temp_dir().join(format!("agg-ws-term-crosses-{}.log", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let mut log = CrossLog::default();
+        log.open(&path).unwrap();
+        let cross = Cross {
+            asset: "BTC".to_string(),
+            bid: (Exchange::Kraken, dec!(101)),
+            ask: (Exchange::Gdax, dec!(100)),
+            size: dec!(1.5),
+            edge_bps: dec!(99.5),
+            net_bps: None,
+        };
+        let start = Instant::now();
+        log.update(std::slice::from_ref(&cross), start);
+        log.update(
+            std::slice::from_ref(&cross),
+            start + Duration::from_millis(250),
+        );
+        assert_eq!(
+            log.duration(&cross, start + Duration::from_millis(250)),
+            Duration::from_millis(250)
+        );
+        log.update(&[], start + Duration::from_millis(400));
+        // A cross still open on shutdown is logged with its duration so far
+        log.update(
+            std::slice::from_ref(&cross),
+            start + Duration::from_millis(500),
+        );
+        log.close(start + Duration::from_millis(600));
+        log.close(start + Duration::from_millis(700));
+        let written = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let lines: Vec<&str> = written.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].ends_with(
+            " asset=BTC bid=Kraken ask=Coinbase max_size=1.5 max_edge_bps=99.5 duration_ms=400"
+        ));
+        assert!(lines[1].ends_with(" duration_ms=100"));
+    }
+}
@@ -0,0 +1,86 @@
+use std::time::Duration;
+
+use agg_ws::client::Exchange;
+use rust_decimal::Decimal;
+
+use crate::book::Levels;
+
+/// Top of one venue's book.
+#[derive(Debug, Clone)]
+pub struct Bbo {
+    pub exchange: Exchange,
+    /// Best bid and ask as (price, size)
+    pub bid: Option<(Decimal, Decimal)>,
+    pub ask: Option<(Decimal, Decimal)>,
+    /// Time since the venue's book last changed
+    pub age: Option<Duration>,
+}
+
+impl Bbo {
+    pub fn new(exchange: Exchange, bids: &Levels, asks: &Levels, age: Option<Duration>) -> Self {
+        Bbo {
+            exchange,
+            bid: bids.iter().next_back().map(|(p, s)| (*p, *s)),
+            ask: asks.iter().next().map(|(p, s)| (*p, *s)),
+            age,
+        }
+    }
+
+    /// Spread relative to the mid in basis points.
+    pub fn spread_bps(&self) -> Option<Decimal> {
+        let (bid, _) = self.bid?;
+        let (ask, _) = self.ask?;
+        let mid = (bid + ask) / Decimal::TWO;
+        (ask - bid)
+            .checked_div(mid)
+            .map(|s| s * Decimal::from(10_000))
+    }
+}
+
+/// Consolidated best bid and best ask prices across venues, leaving out stale venues whose
+/// quotes may no longer be live.
+pub fn best(bbos: &[Bbo], stale: &[Exchange]) -> (Option<Decimal>, Option<Decimal>) {
+    let live = || bbos.iter().filter(|b| !stale.contains(&b.exchange));
+    let bid = live().filter_map(|b| b.bid.map(|(p, _)| p)).max();
+    let ask = live().filter_map(|b| b.ask.map(|(p, _)| p)).min();
+    (bid, ask)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    fn bbo(exchange: Exchange, bid: Decimal, ask: Decimal) -> Bbo {
+        let bids = [(bid, dec!(1)), (bid - dec!(1), dec!(2))]
+            .into_iter()
+            .collect();
+        let asks = [(ask, dec!(3)), (ask + dec!(1), dec!(4))]
+            .into_iter()
+            .collect();
+        Bbo::new(exchange, &bids, &asks, None)
+    }
+
+    #[test]
+    pub fn top_of_book() {
+        let kraken = bbo(Exchange::Kraken, dec!(99), dec!(101));
+        assert_eq!(kraken.bid, Some((dec!(99), dec!(1))));
+        assert_eq!(kraken.ask, Some((dec!(101), dec!(3))));
+        assert_eq!(kraken.spread_bps(), Some(dec!(200)));
+        let coinbase = bbo(Exchange::Gdax, dec!(99.5), dec!(101.5));
+        let venues = [kraken, coinbase];
+        assert_eq!(best(&venues, &[]), (Some(dec!(99.5)), Some(dec!(101))));
+        // A stale venue holding the best bid is passed over for the best live one
+        assert_eq!(
+            best(&venues, &[Exchange::Gdax]),
+            (Some(dec!(99)), Some(dec!(101)))
+        );
+        assert_eq!(
+            best(&venues, &[Exchange::Kraken, Exchange::Gdax]),
+            (None, None)
+        );
+        let empty = Bbo::new(Exchange::Hyperliquid, &Levels::new(), &Levels::new(), None);
+        assert!(empty.spread_bps().is_none());
+        assert_eq!(best(&[empty], &[]), (None, None));
+    }
+}
@@ -0,0 +1,342 @@
+use std::collections::BTreeMap;
+
+use agg_ws::client::Exchange;
+use rust_decimal::{Decimal, RoundingStrategy};
+
+/// One side of a single venue's book, price to size.
+pub type Levels = BTreeMap<Decimal, Decimal>;
+
+#[derive(Debug, Clone)]
+pub enum AggExchange {
+    Aggregate,
+    Exchange(Exchange),
+}
+
+impl AggExchange {
+    pub fn as_display(&self) -> &'static str {
+        match self {
+            Self::Aggregate => "Aggregate",
+            Self::Exchange(Exchange::Gdax) => "Coinbase",
+            Self::Exchange(Exchange::Kraken) => "Kraken",
+            Self::Exchange(Exchange::Hyperliquid) => "Hyperliquid",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct AggBook {
+    pub bids: BTreeMap<Decimal, Level>,
+    pub asks: BTreeMap<Decimal, Level>,
+}
+
+impl AggBook {
+    pub fn new() -> Self {
+        AggBook {
+            bids: BTreeMap::new(),
+            asks: BTreeMap::new(),
+        }
+    }
+
+    /// Book of a single venue.
+    pub fn from_exchange(exchange: Exchange, bids: &Levels, asks: &Levels) -> Self {
+        let mut agg_book = AggBook::new();
+        agg_book.merge(exchange, bids, asks);
+        agg_book
+    }
+
+    /// Add a venue's levels on top of the levels already in the book.
+    pub fn merge(&mut self, exchange: Exchange, bids: &Levels, asks: &Levels) {
+        merge_levels(&mut self.bids, exchange, bids);
+        merge_levels(&mut self.asks, exchange, asks);
+    }
+
+    /// The `depth` best levels of each side.
+    pub fn top(&self, depth: usize) -> AggBook {
+        AggBook {
+            bids: self
+                .bids
+                .iter()
+                .rev()
+                .take(depth)
+                .map(|(p, l)| (*p, l.clone()))
+                .collect(),
+            asks: self
+                .asks
+                .iter()
+                .take(depth)
+                .map(|(p, l)| (*p, l.clone()))
+                .collect(),
+        }
+    }
+
+    /// The `depth` best levels of each side bucketed to multiples of `grouping`. Bids are rounded
+    /// down and asks up so a bucket never shows a better price than the levels in it. Only the
+    /// levels in those buckets are visited.
+    pub fn grouped(&self, grouping: Decimal, depth: usize) -> AggBook {
+        AggBook {
+            bids: group_levels(
+                self.bids.iter().rev(),
+                grouping,
+                RoundingStrategy::ToNegativeInfinity,
+                depth,
+            ),
+            asks: group_levels(
+                self.asks.iter(),
+                grouping,
+                RoundingStrategy::ToPositiveInfinity,
+                depth,
+            ),
+        }
+    }
+}
+
+impl Default for AggBook {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Total size at a price and how much of it each venue quotes, in the order venues were merged.
+#[derive(Debug, Clone)]
+pub struct Level {
+    pub size: Decimal,
+    pub venues: Vec<(Exchange, Decimal)>,
+}
+
+impl Level {
+    fn new(exchange: Exchange, size: Decimal) -> Self {
+        Level {
+            size,
+            venues: vec![(exchange, size)],
+        }
+    }
+
+    fn add(&mut self, exchange: Exchange, size: Decimal) {
+        self.size += size;
+        match self.venues.iter_mut().find(|(ex, _)| *ex == exchange) {
+            Some((_, s)) => *s += size,
+            None => self.venues.push((exchange, size)),
+        }
+    }
+
+    /// The venue quoting the level, or Aggregate if more than one does.
+    pub fn exchange(&self) -> AggExchange {
+        match self.venues.as_slice() {
+            [(exchange, _)] => AggExchange::Exchange(*exchange),
+            _ => AggExchange::Aggregate,
+        }
+    }
+}
+
+fn merge_levels(side: &mut BTreeMap<Decimal, Level>, exchange: Exchange, levels: &Levels) {
+    for (price, size) in levels.iter() {
+        side.entry(*price)
+            .and_modify(|l| l.add(exchange, *size))
+            .or_insert_with(|| Level::new(exchange, *size));
+    }
+}
+
+// Levels are taken from the inside out, so the first level of a bucket past `depth` ends it
+fn group_levels<'a>(
+    side: impl Iterator<Item = (&'a Decimal, &'a Level)>,
+    grouping: Decimal,
+    strategy: RoundingStrategy,
+    depth: usize,
+) -> BTreeMap<Decimal, Level> {
+    let mut grouped: BTreeMap<Decimal, Level> = BTreeMap::new();
+    for (price, level) in side {
+        let bucket = (price / grouping).round_dp_with_strategy(0, strategy) * grouping;
+        let full = grouped.len() == depth;
+        match grouped.get_mut(&bucket) {
+            Some(l) => {
+                for (exchange, size) in level.venues.iter() {
+                    l.add(*exchange, *size);
+                }
+            }
+            None if full => break,
+            None => {
+                grouped.insert(bucket, level.clone());
+            }
+        }
+    }
+    grouped
+}
+
+/// Update one side of an aggregated book after a venue's levels changed. Only the `changed`
+/// prices are re-aggregated, from `venues`, the current levels of every venue in the book
+/// including the one that changed. Unchanged levels are left untouched.
+pub fn apply_delta(
+    side: &mut BTreeMap<Decimal, Level>,
+    changed: &[Decimal],
+    venues: &[(Exchange, &Levels)],
+) {
+    for price in changed {
+        match aggregate_level(price, venues) {
+            Some(level) => side.insert(*price, level),
+            None => side.remove(price),
+        };
+    }
+}
+
+/// Prices added, removed or resized between two books, found in a single pass over both. The
+/// feeds send whole books, so this pass is the only work that grows with a venue's depth.
+pub fn changed_prices(prev: &Levels, next: &Levels) -> Vec<Decimal> {
+    let mut changed = Vec::new();
+    let mut prev = prev.iter().peekable();
+    let mut next = next.iter().peekable();
+    loop {
+        match (prev.peek(), next.peek()) {
+            (Some((p, _)), Some((n, _))) if p < n => {
+                changed.push(**p);
+                prev.next();
+            }
+            (Some((p, _)), Some((n, _))) if p > n => {
+                changed.push(**n);
+                next.next();
+            }
+            (Some((p, ps)), Some((_, ns))) => {
+                if ps != ns {
+                    changed.push(**p);
+                }
+                prev.next();
+                next.next();
+            }
+            (Some((p, _)), None) => {
+                changed.push(**p);
+                prev.next();
+            }
+            (None, Some((n, _))) => {
+                changed.push(**n);
+                next.next();
+            }
+            (None, None) => break,
+        }
+    }
+    changed
+}
+
+// Sum of every venue's size at a price, or None if no venue quotes it
+fn aggregate_level(price: &Decimal, venues: &[(Exchange, &Levels)]) -> Option<Level> {
+    let mut level: Option<Level> = None;
+    for (exchange, levels) in venues.iter() {
+        if let Some(size) = levels.get(price) {
+            match level.as_mut() {
+                Some(l) => l.add(*exchange, *size),
+                None => level = Some(Level::new(*exchange, *size)),
+            }
+        }
+    }
+    level
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    fn levels(levels: &[(Decimal, Decimal)]) -> Levels {
+        levels.iter().cloned().collect()
+    }
+
+    fn sizes(side: &BTreeMap<Decimal, Level>) -> Vec<(Decimal, Decimal, &'static str)> {
+        side.iter()
+            .map(|(p, l)| (*p, l.size, l.exchange().as_display()))
+            .collect()
+    }
+
+    #[test]
+    pub fn delta_matches_rebuild() {
+        let kraken = levels(&[
+            (dec!(100), dec!(1)),
+            (dec!(99), dec!(2)),
+            (dec!(98), dec!(3)),
+        ]);
+        let coinbase = levels(&[(dec!(100), dec!(0.5)), (dec!(97), dec!(4))]);
+        let mut side = BTreeMap::new();
+        merge_levels(&mut side, Exchange::Kraken, &kraken);
+        merge_levels(&mut side, Exchange::Gdax, &coinbase);
+
+        // Coinbase leaves 100, adds 99 and resizes 97
+        let next = levels(&[(dec!(99), dec!(1)), (dec!(97), dec!(5))]);
+        apply_delta(
+            &mut side,
+            &changed_prices(&coinbase, &next),
+            &[(Exchange::Kraken, &kraken), (Exchange::Gdax, &next)],
+        );
+
+        let mut rebuilt = BTreeMap::new();
+        merge_levels(&mut rebuilt, Exchange::Kraken, &kraken);
+        merge_levels(&mut rebuilt, Exchange::Gdax, &next);
+        assert_eq!(sizes(&side), sizes(&rebuilt));
+        assert_eq!(
+            side.get(&dec!(99)).unwrap().venues,
+            vec![(Exchange::Kraken, dec!(2)), (Exchange::Gdax, dec!(1))]
+        );
+        assert_eq!(
+            sizes(&side),
+            vec![
+                (dec!(97), dec!(5), "Coinbase"),
+                (dec!(98), dec!(3), "Kraken"),
+                (dec!(99), dec!(3), "Aggregate"),
+                (dec!(100), dec!(1), "Kraken"),
+            ]
+        );
+
+        // Kraken disconnects
+        let empty = Levels::new();
+        apply_delta(
+            &mut side,
+            &changed_prices(&kraken, &empty),
+            &[(Exchange::Gdax, &next)],
+        );
+        assert_eq!(
+            sizes(&side),
+            vec![
+                (dec!(97), dec!(5), "Coinbase"),
+                (dec!(99), dec!(1), "Coinbase")
+            ]
+        );
+    }
+
+    #[test]
+    pub fn grouping() {
+        let mut book = AggBook::new();
+        book.merge(
+            Exchange::Kraken,
+            &levels(&[(dec!(99.95), dec!(1)), (dec!(99.91), dec!(2))]),
+            &levels(&[(dec!(100.01), dec!(1)), (dec!(100.1), dec!(2))]),
+        );
+        book.merge(
+            Exchange::Gdax,
+            &levels(&[(dec!(99.9), dec!(3))]),
+            &levels(&[(dec!(100.05), dec!(4))]),
+        );
+        let grouped = book.grouped(dec!(0.1), 10);
+        assert_eq!(
+            sizes(&grouped.bids),
+            vec![(dec!(99.9), dec!(6), "Aggregate")]
+        );
+        // Venues in the order they first quote inside the bucket, from the best price
+        assert_eq!(
+            grouped.bids.get(&dec!(99.9)).unwrap().venues,
+            vec![(Exchange::Kraken, dec!(3)), (Exchange::Gdax, dec!(3))]
+        );
+        assert_eq!(
+            sizes(&grouped.asks),
+            vec![(dec!(100.1), dec!(7), "Aggregate")]
+        );
+        let grouped = book.grouped(dec!(1), 10);
+        assert_eq!(sizes(&grouped.bids), vec![(dec!(99), dec!(6), "Aggregate")]);
+        assert_eq!(
+            sizes(&grouped.asks),
+            vec![(dec!(101), dec!(7), "Aggregate")]
+        );
+        // Only the best buckets are built, each complete
+        let grouped = book.grouped(dec!(0.05), 1);
+        assert_eq!(sizes(&grouped.bids), vec![(dec!(99.95), dec!(1), "Kraken")]);
+        assert_eq!(
+            sizes(&grouped.asks),
+            vec![(dec!(100.05), dec!(5), "Aggregate")]
+        );
+    }
+}
@@ -0,0 +1,97 @@
+use std::path::PathBuf;
+
+use clap::{Parser, Subcommand};
+
+/// Aggregated order book and trade tape terminal
+#[derive(Debug, Parser)]
+#[command(version, about)]
+pub struct Cli {
+    /// Path to the config file, overriding the default search path
+    #[arg(short, long, global = true)]
+    pub config: Option<PathBuf>,
+
+    /// Asset selected at startup, e.g. BTC
+    #[arg(short, long, global = true)]
+    pub asset: Option<String>,
+
+    /// Only use these exchanges, e.g. kraken,coinbase
+    #[arg(short, long, global = true, value_delimiter = ',')]
+    pub exchanges: Vec<String>,
+
+    /// Refresh interval in milliseconds
+    #[arg(
+        short,
+        long,
+        global = true,
+        default_value_t = 350,
+        value_parser = clap::value_parser!(u64).range(1..)
+    )]
+    pub interval: u64,
+
+    /// Maximum redraws per second, up to 1000
+    #[arg(
+        long,
+        global = true,
+        default_value_t = 30,
+        value_parser = clap::value_parser!(u32).range(1..=1000)
+    )]
+    pub fps: u32,
+
+    /// Poll every channel each interval instead of backing off from quiet channels
+    #[arg(long, global = true)]
+    pub fixed_poll: bool,
+
+    /// Simulate the exchanges instead of connecting to them
+    #[arg(long, global = true)]
+    pub demo: bool,
+
+    /// Record every message received to the configured recordings directory
+    #[arg(long, global = true)]
+    pub record: bool,
+
+    /// Log file path
+    #[arg(long, global = true, default_value = "debug.log")]
+    pub log_file: PathBuf,
+
+    /// Log level: trace, debug, info, warn or error
+    #[arg(long, global = true, default_value_t = tracing::Level::INFO)]
+    pub log_level: tracing::Level,
+
+    #[command(subcommand)]
+    pub mode: Option<Mode>,
+}
+
+#[derive(Debug, Clone, Subcommand)]
+pub enum Mode {
+    /// Interactive terminal UI (default)
+    Tui,
+    /// Subscribe and maintain state without a terminal UI, logging to the log file
+    Headless,
+    /// Play back recorded sessions in the terminal UI without connecting to the exchanges
+    Replay {
+        /// Recording files, played back as one session
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+    },
+}
+
+impl Cli {
+    pub fn mode(&self) -> Mode {
+        self.mode.clone().unwrap_or(Mode::Tui)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn rejects_zero_rates() {
+        let parse = |args: &[&str]| Cli::try_parse_from([&["agg-ws-term"], args].concat());
+        let cli = parse(&["--interval", "100", "--fps", "60"]).unwrap();
+        assert_eq!((cli.interval, cli.fps), (100, 60));
+        assert!(parse(&["--interval", "0"]).is_err());
+        assert!(parse(&["--fps", "0"]).is_err());
+        assert!(parse(&["--fps", "4294967295"]).is_err());
+    }
+}
@@ -0,0 +1,787 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+use agg_ws::client::Exchange;
+use anyhow::{anyhow, bail, Context};
+use rust_decimal::prelude::*;
+use rust_decimal_macros::dec;
+use serde::Deserialize;
+
+use crate::symbols::Instrument;
+
+// Shipped config, used when no file is found on the search path
+const DEFAULT_CONFIG: &str = include_str!("../agg-ws-term.toml");
+const CONFIG_FILE: &str = "agg-ws-term.toml";
+// Book price groupings for assets that do not list their own
+const DEFAULT_GROUPINGS: [Decimal; 4] = [dec!(0.01), dec!(0.1), dec!(1), dec!(10)];
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub default_asset: usize,
+    pub assets: Vec<AssetConfig>,
+    pub subscriptions: SubscriptionConfig,
+    pub display: DisplayConfig,
+    pub feeds: FeedConfig,
+    pub arbitrage: ArbitrageConfig,
+    pub record: RecordConfig,
+    pub demo: DemoConfig,
+}
+
+/// Crossed market detection between venues of the same asset.
+#[derive(Debug, Clone, Default)]
+pub struct ArbitrageConfig {
+    /// Taker fee of each venue in basis points, subtracted from the gross edge when set
+    pub fees_bps: HashMap<Exchange, Decimal>,
+    /// File each crossed market is appended to once it closes
+    pub log_file: Option<PathBuf>,
+}
+
+/// How much market data is kept for display. Rows shown are further limited to the screen size.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DisplayConfig {
+    /// Book levels per side
+    pub book_depth: usize,
+    /// Most recent trades
+    pub tape_depth: usize,
+}
+
+impl Default for DisplayConfig {
+    fn default() -> Self {
+        DisplayConfig {
+            book_depth: 100,
+            tape_depth: 100,
+        }
+    }
+}
+
+/// Where `--record` writes sessions and when it starts a new file.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RecordConfig {
+    pub dir: PathBuf,
+    pub max_file_mb: u64,
+    pub max_file_secs: u64,
+}
+
+impl Default for RecordConfig {
+    fn default() -> Self {
+        RecordConfig {
+            dir: PathBuf::from("recordings"),
+            max_file_mb: 100,
+            max_file_secs: 3_600,
+        }
+    }
+}
+
+/// Simulated venues used with `--demo`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DemoConfig {
+    /// Each venue's spread around its own mid in basis points
+    pub spread_bps: f64,
+    /// Book levels per side
+    pub depth: usize,
+    /// Standard deviation of the mid's moves over one second in basis points
+    pub volatility_bps: f64,
+    /// Average trades per second on each venue
+    pub trades_per_sec: f64,
+    /// How often each venue sends its book
+    pub book_ms: u64,
+    /// Seed for a repeatable session, random if unset
+    pub seed: Option<u64>,
+    /// Starting mid and tick size of each asset. Other assets start at 100 with their finest book
+    /// grouping as the tick.
+    pub assets: HashMap<String, DemoAsset>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DemoAsset {
+    pub price: f64,
+    pub tick: Option<f64>,
+}
+
+impl Default for DemoConfig {
+    fn default() -> Self {
+        let asset = |price, tick| DemoAsset {
+            price,
+            tick: Some(tick),
+        };
+        DemoConfig {
+            spread_bps: 2.0,
+            depth: 50,
+            volatility_bps: 3.0,
+            trades_per_sec: 2.0,
+            book_ms: 250,
+            seed: None,
+            assets: HashMap::from([
+                ("BTC".to_string(), asset(65_000.0, 0.1)),
+                ("ETH".to_string(), asset(3_500.0, 0.01)),
+                ("SOL".to_string(), asset(150.0, 0.01)),
+            ]),
+        }
+    }
+}
+
+/// When a venue feed that has stopped changing is treated as stale.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FeedConfig {
+    /// Books unchanged for this long are marked stale and left out of crossed market detection
+    pub stale_ms: u64,
+    /// Books unchanged for this long are dropped from the aggregated book until they change again
+    pub exclude_stale_ms: Option<u64>,
+}
+
+impl Default for FeedConfig {
+    fn default() -> Self {
+        FeedConfig {
+            stale_ms: 5_000,
+            exclude_stale_ms: None,
+        }
+    }
+}
+
+/// Which assets keep their channels subscribed once they are no longer being viewed.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SubscriptionConfig {
+    /// Number of most recently viewed assets kept subscribed besides the selected one
+    pub keep_recent: usize,
+    /// Unsubscribe assets not viewed for this many seconds, even if recent
+    pub idle_timeout_secs: Option<u64>,
+    /// Delay before the first retry of a failed subscribe, doubled on each further failure
+    pub retry_base_ms: u64,
+    pub retry_max_ms: u64,
+    /// Failed subscribe requests before giving up on a channel
+    pub max_attempts: u32,
+    /// Subscribe requests in flight at once
+    pub max_parallel: usize,
+    pub request_timeout_ms: u64,
+}
+
+impl Default for SubscriptionConfig {
+    fn default() -> Self {
+        SubscriptionConfig {
+            keep_recent: 2,
+            idle_timeout_secs: None,
+            retry_base_ms: 500,
+            retry_max_ms: 30_000,
+            max_attempts: 6,
+            max_parallel: 8,
+            request_timeout_ms: 5_000,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct AssetConfig {
+    pub name: String,
+    pub instrument: Instrument,
+    pub size_dp: u32,
+    pub venues: Vec<Exchange>,
+    pub tickers: HashMap<Exchange, String>,
+    /// Price increments the book can be grouped by, finest first
+    pub groupings: Vec<Decimal>,
+    /// Index into `groupings` used at startup, ungrouped if None
+    pub grouping: Option<usize>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawConfig {
+    default_asset: Option<String>,
+    assets: Vec<RawAsset>,
+    #[serde(default)]
+    subscriptions: SubscriptionConfig,
+    #[serde(default)]
+    display: DisplayConfig,
+    #[serde(default)]
+    feeds: FeedConfig,
+    #[serde(default)]
+    arbitrage: RawArbitrage,
+    #[serde(default)]
+    record: RecordConfig,
+    #[serde(default)]
+    demo: DemoConfig,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawArbitrage {
+    #[serde(default)]
+    fees_bps: HashMap<String, f64>,
+    log_file: Option<PathBuf>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawAsset {
+    name: String,
+    base: Option<String>,
+    quote: Option<String>,
+    size_dp: u32,
+    venues: Vec<String>,
+    #[serde(default)]
+    symbols: HashMap<String, String>,
+    #[serde(default)]
+    groupings: Vec<f64>,
+    grouping: Option<f64>,
+}
+
+pub fn parse_exchange(s: &str) -> Option<Exchange> {
+    match s.to_lowercase().as_str() {
+        "kraken" => Some(Exchange::Kraken),
+        "coinbase" | "gdax" => Some(Exchange::Gdax),
+        "hyperliquid" => Some(Exchange::Hyperliquid),
+        _ => None,
+    }
+}
+
+/// Name of an exchange as written in the config, the inverse of `parse_exchange`.
+pub fn exchange_name(exchange: Exchange) -> &'static str {
+    match exchange {
+        Exchange::Kraken => "kraken",
+        Exchange::Gdax => "coinbase",
+        Exchange::Hyperliquid => "hyperliquid",
+    }
+}
+
+impl Config {
+    /// Load the config from `path` if given, otherwise from the first file found on the default
+    /// search path, falling back to the shipped config.
+    pub fn load(path: Option<&Path>) -> anyhow::Result<Config> {
+        let path = match path {
+            Some(p) => Some(p.to_path_buf()),
+            None => Self::default_paths().into_iter().find(|p| p.is_file()),
+        };
+        match path {
+            Some(p) => {
+                tracing::info!("Loading config from {}", p.display());
+                let s = fs::read_to_string(&p)
+                    .with_context(|| format!("failed to read config file {}", p.display()))?;
+                Self::parse(&s).with_context(|| format!("invalid config file {}", p.display()))
+            }
+            None => {
+                tracing::info!("No config file found, using built-in defaults.");
+                Self::parse(DEFAULT_CONFIG)
+            }
+        }
+    }
+
+    pub fn default_paths() -> Vec<PathBuf> {
+        let mut paths = vec![PathBuf::from(CONFIG_FILE)];
+        if let Some(dir) = env::var_os("XDG_CONFIG_HOME") {
+            paths.push(PathBuf::from(dir).join("agg-ws-term").join("config.toml"));
+        }
+        if let Some(dir) = env::var_os("HOME") {
+            paths.push(
+                PathBuf::from(dir)
+                    .join(".config")
+                    .join("agg-ws-term")
+                    .join("config.toml"),
+            );
+        }
+        paths
+    }
+
+    pub fn parse(s: &str) -> anyhow::Result<Config> {
+        let raw: RawConfig = toml::from_str(s)?;
+        if raw.assets.is_empty() {
+            bail!("no assets configured");
+        }
+        if raw.subscriptions.max_parallel == 0 {
+            bail!("subscriptions.max_parallel must be at least 1");
+        }
+        if raw.record.max_file_mb == 0 || raw.record.max_file_secs == 0 {
+            bail!("record.max_file_mb and record.max_file_secs must be at least 1");
+        }
+        if matches!(raw.feeds.exclude_stale_ms, Some(ms) if ms < raw.feeds.stale_ms) {
+            bail!("feeds.exclude_stale_ms must be at least feeds.stale_ms");
+        }
+        raw.demo.validate()?;
+        let mut assets = Vec::with_capacity(raw.assets.len());
+        for raw_asset in raw.assets {
+            let asset = AssetConfig::validate(raw_asset)?;
+            if assets.iter().any(|a: &AssetConfig| a.name == asset.name) {
+                bail!("asset {} is configured more than once", asset.name);
+            }
+            assets.push(asset);
+        }
+        let default_asset = match raw.default_asset {
+            Some(name) => assets
+                .iter()
+                .position(|a| a.name == name)
+                .ok_or_else(|| anyhow!("default_asset {} is not a configured asset", name))?,
+            None => 0,
+        };
+        let mut fees_bps = HashMap::new();
+        for (exchange, fee) in raw.arbitrage.fees_bps.iter() {
+            let ex = parse_exchange(exchange)
+                .ok_or_else(|| anyhow!("arbitrage.fees_bps: unknown exchange {}", exchange))?;
+            let fee = Decimal::from_f64(*fee)
+                .ok_or_else(|| anyhow!("arbitrage.fees_bps: invalid fee {}", fee))?;
+            fees_bps.insert(ex, fee);
+        }
+        Ok(Config {
+            default_asset,
+            assets,
+            subscriptions: raw.subscriptions,
+            display: raw.display,
+            feeds: raw.feeds,
+            arbitrage: ArbitrageConfig {
+                fees_bps,
+                log_file: raw.arbitrage.log_file,
+            },
+            record: raw.record,
+            demo: raw.demo,
+        })
+    }
+
+    /// Set the asset selected at startup.
+    pub fn select_asset(&mut self, name: &str) -> anyhow::Result<()> {
+        self.default_asset = self
+            .assets
+            .iter()
+            .position(|a| a.name.eq_ignore_ascii_case(name))
+            .ok_or_else(|| anyhow!("asset {} is not a configured asset", name))?;
+        Ok(())
+    }
+
+    /// Replace the assets with those in a recording's header, keeping the display settings of
+    /// configured assets of the same name. The selected asset is kept if it was recorded. Venues
+    /// keep the order recorded with them or, for older recordings, the configured order.
+    pub fn replay_assets(
+        &mut self,
+        recorded: &BTreeMap<String, BTreeMap<String, String>>,
+        recorded_venues: &BTreeMap<String, Vec<String>>,
+    ) -> anyhow::Result<()> {
+        let selected = self.assets[self.default_asset].name.clone();
+        let mut assets = Vec::with_capacity(recorded.len());
+        for (name, markets) in recorded.iter() {
+            let order: Vec<&str> = match recorded_venues.get(name) {
+                Some(venues) => venues.iter().map(|v| v.as_str()).collect(),
+                None => self
+                    .assets
+                    .iter()
+                    .find(|a| a.name == *name)
+                    .map(|a| a.venues.iter().map(|ex| exchange_name(*ex)).collect())
+                    .unwrap_or_default(),
+            };
+            let mut markets: Vec<(&String, &String)> = markets.iter().collect();
+            markets.sort_by_key(|(ex, _)| order.iter().position(|o| o == ex).unwrap_or(usize::MAX));
+            let venues: Vec<String> = markets
+                .iter()
+                .map(|(ex, market)| format!("{}={}", ex, market))
+                .collect();
+            let mut asset = AssetConfig::parse_spec(&format!("{} {}", name, venues.join(" ")))
+                .map_err(|e| anyhow!("recorded {}", e))?;
+            if let Some(configured) = self.assets.iter().find(|a| a.name == asset.name) {
+                asset.instrument = configured.instrument.clone();
+                asset.size_dp = configured.size_dp;
+                asset.groupings = configured.groupings.clone();
+                asset.grouping = configured.grouping;
+            }
+            assets.push(asset);
+        }
+        if assets.is_empty() {
+            bail!("the recording has no assets");
+        }
+        self.default_asset = assets.iter().position(|a| a.name == selected).unwrap_or(0);
+        self.assets = assets;
+        Ok(())
+    }
+
+    /// Drop every venue not in `exchanges` from all assets, and assets left with no venues. The
+    /// selected asset must keep at least one venue.
+    pub fn restrict_venues(&mut self, exchanges: &[String]) -> anyhow::Result<()> {
+        let mut keep = Vec::with_capacity(exchanges.len());
+        for exchange in exchanges.iter() {
+            keep.push(
+                parse_exchange(exchange).ok_or_else(|| anyhow!("unknown exchange {}", exchange))?,
+            );
+        }
+        let selected = self.assets[self.default_asset].name.clone();
+        for asset in self.assets.iter_mut() {
+            asset.venues.retain(|ex| keep.contains(ex));
+            asset.tickers.retain(|ex, _| keep.contains(ex));
+        }
+        self.assets.retain(|a| !a.venues.is_empty());
+        if self.assets.is_empty() {
+            bail!("no configured asset trades on {}", exchanges.join(", "));
+        }
+        self.default_asset = self
+            .assets
+            .iter()
+            .position(|a| a.name == selected)
+            .ok_or_else(|| {
+                anyhow!(
+                    "--asset {} does not trade on --exchanges {}",
+                    selected,
+                    exchanges.join(",")
+                )
+            })?;
+        Ok(())
+    }
+}
+
+impl DemoConfig {
+    fn validate(&self) -> anyhow::Result<()> {
+        if self.spread_bps <= 0.0 || self.depth == 0 || self.book_ms == 0 {
+            bail!("demo.spread_bps, demo.depth and demo.book_ms must be positive");
+        }
+        if self.volatility_bps < 0.0 || self.trades_per_sec < 0.0 {
+            bail!("demo.volatility_bps and demo.trades_per_sec must not be negative");
+        }
+        for (name, asset) in self.assets.iter() {
+            if asset.price <= 0.0 || asset.tick.is_some_and(|t| t <= 0.0) {
+                bail!("demo.assets.{}: price and tick must be positive", name);
+            }
+        }
+        Ok(())
+    }
+
+    /// Starting mid and tick size of an asset.
+    pub fn asset(&self, asset: &AssetConfig) -> (f64, Decimal) {
+        let demo = self
+            .assets
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(&asset.name))
+            .map(|(_, a)| a);
+        let price = demo.map(|a| a.price).unwrap_or(100.0);
+        let tick = demo
+            .and_then(|a| a.tick)
+            .and_then(Decimal::from_f64)
+            .unwrap_or(asset.groupings[0]);
+        (price, tick)
+    }
+}
+
+impl AssetConfig {
+    /// Parse an asset from a single line of the form `BASE[/QUOTE] [size_dp] exchange[=symbol] ...`,
+    /// using every listed exchange as a venue. Size decimals default to 8 and symbols not given
+    /// are looked up from the instrument.
+    pub fn parse_spec(s: &str) -> anyhow::Result<AssetConfig> {
+        let mut tokens = s.split_whitespace();
+        let name = tokens
+            .next()
+            .ok_or_else(|| anyhow!("expected BASE[/QUOTE] [size_dp] exchange[=symbol] ..."))?
+            .to_uppercase();
+        let instrument = Instrument::parse(&name);
+        let mut raw = RawAsset {
+            name,
+            base: Some(instrument.base),
+            quote: Some(instrument.quote),
+            size_dp: 8,
+            venues: Vec::new(),
+            symbols: HashMap::new(),
+            groupings: Vec::new(),
+            grouping: None,
+        };
+        for token in tokens {
+            if let Ok(dp) = token.parse() {
+                raw.size_dp = dp;
+                continue;
+            }
+            match token.split_once('=') {
+                Some((exchange, symbol)) => {
+                    raw.venues.push(exchange.to_string());
+                    raw.symbols.insert(exchange.to_string(), symbol.to_string());
+                }
+                None => raw.venues.push(token.to_string()),
+            }
+        }
+        Self::validate(raw)
+    }
+
+    fn validate(raw: RawAsset) -> anyhow::Result<AssetConfig> {
+        if raw.venues.is_empty() {
+            bail!("asset {} has no venues", raw.name);
+        }
+        let default = Instrument::parse(&raw.name);
+        let instrument = Instrument::new(
+            raw.base.as_deref().unwrap_or(&default.base),
+            raw.quote.as_deref().unwrap_or(&default.quote),
+        );
+        let mut symbols = HashMap::new();
+        for (exchange, symbol) in raw.symbols.iter() {
+            let ex = parse_exchange(exchange)
+                .ok_or_else(|| anyhow!("asset {}: unknown exchange {}", raw.name, exchange))?;
+            symbols.insert(ex, symbol.clone());
+        }
+        let mut venues = Vec::with_capacity(raw.venues.len());
+        let mut tickers = HashMap::new();
+        for venue in raw.venues.iter() {
+            let ex = parse_exchange(venue)
+                .ok_or_else(|| anyhow!("asset {}: unknown exchange {}", raw.name, venue))?;
+            if venues.contains(&ex) {
+                bail!(
+                    "asset {}: venue {} is listed more than once",
+                    raw.name,
+                    venue
+                );
+            }
+            // Explicit symbols override the registry
+            let symbol = symbols
+                .get(&ex)
+                .cloned()
+                .or_else(|| instrument.market(ex))
+                .ok_or_else(|| anyhow!("asset {}: no symbol for venue {}", raw.name, venue))?;
+            venues.push(ex);
+            tickers.insert(ex, symbol);
+        }
+        let mut groupings = Vec::with_capacity(raw.groupings.len());
+        for g in raw.groupings.iter() {
+            match Decimal::from_f64(*g) {
+                Some(d) if d > Decimal::ZERO => groupings.push(d.normalize()),
+                _ => bail!("asset {}: grouping {} must be positive", raw.name, g),
+            }
+        }
+        if groupings.is_empty() {
+            groupings = DEFAULT_GROUPINGS.to_vec();
+        }
+        groupings.sort();
+        groupings.dedup();
+        let grouping = match raw.grouping {
+            Some(g) => Some(
+                Decimal::from_f64(g)
+                    .and_then(|d| groupings.iter().position(|x| *x == d.normalize()))
+                    .ok_or_else(|| {
+                        anyhow!("asset {}: grouping {} is not in its groupings", raw.name, g)
+                    })?,
+            ),
+            None => None,
+        };
+        Ok(AssetConfig {
+            name: raw.name,
+            instrument,
+            size_dp: raw.size_dp,
+            venues,
+            tickers,
+            groupings,
+            grouping,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn default_config_is_valid() {
+        let config = Config::parse(DEFAULT_CONFIG).unwrap();
+        assert_eq!(config.assets.len(), 3);
+        assert_eq!(config.assets[config.default_asset].name, "SOL");
+        assert_eq!(
+            config.assets[0].tickers.get(&Exchange::Kraken).unwrap(),
+            "XBT/USD"
+        );
+        assert_eq!(config.subscriptions.keep_recent, 2);
+        assert!(config.subscriptions.idle_timeout_secs.is_none());
+        assert_eq!(config.display.book_depth, 100);
+        assert_eq!(config.feeds.stale_ms, 5_000);
+        assert_eq!(
+            config.arbitrage.fees_bps.get(&Exchange::Gdax),
+            Some(&dec!(60))
+        );
+    }
+
+    #[test]
+    pub fn unknown_exchange() {
+        let s = r#"
+            [[assets]]
+            name = "BTC"
+            size_dp = 8
+            venues = ["binance"]
+            [assets.symbols]
+            binance = "BTCUSDT"
+        "#;
+        let err = Config::parse(s).unwrap_err();
+        assert_eq!(err.to_string(), "asset BTC: unknown exchange binance");
+    }
+
+    #[test]
+    pub fn missing_symbol() {
+        let s = r#"
+            assets = [
+                { name = "ETH/BTC", size_dp = 8, venues = ["kraken", "hyperliquid"] },
+            ]
+        "#;
+        let err = Config::parse(s).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "asset ETH/BTC: no symbol for venue hyperliquid"
+        );
+    }
+
+    #[test]
+    pub fn symbol_override() {
+        let s = r#"
+            [[assets]]
+            name = "BTC"
+            size_dp = 8
+            venues = ["kraken", "coinbase"]
+            [assets.symbols]
+            coinbase = "BTC-USDT"
+        "#;
+        let config = Config::parse(s).unwrap();
+        let asset = &config.assets[0];
+        assert_eq!(asset.tickers.get(&Exchange::Kraken).unwrap(), "XBT/USD");
+        assert_eq!(asset.tickers.get(&Exchange::Gdax).unwrap(), "BTC-USDT");
+    }
+
+    #[test]
+    pub fn groupings() {
+        let s = r#"
+            assets = [
+                { name = "SOL", size_dp = 3, venues = ["kraken"], groupings = [1, 0.01, 0.1], grouping = 0.1 },
+            ]
+        "#;
+        let config = Config::parse(s).unwrap();
+        let asset = &config.assets[0];
+        assert_eq!(asset.groupings, vec![dec!(0.01), dec!(0.1), dec!(1)]);
+        assert_eq!(asset.grouping, Some(1));
+        let err = Config::parse(&s.replace("grouping = 0.1", "grouping = 5")).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "asset SOL: grouping 5 is not in its groupings"
+        );
+        let err = Config::parse(&s.replace("groupings = [1,", "groupings = [-1,")).unwrap_err();
+        assert_eq!(err.to_string(), "asset SOL: grouping -1 must be positive");
+    }
+
+    #[test]
+    pub fn stale_feeds() {
+        let s = r#"
+            assets = [{ name = "BTC", size_dp = 8, venues = ["kraken"] }]
+            [feeds]
+            stale_ms = 2000
+            exclude_stale_ms = 10000
+        "#;
+        let config = Config::parse(s).unwrap();
+        assert_eq!(config.feeds.stale_ms, 2_000);
+        assert_eq!(config.feeds.exclude_stale_ms, Some(10_000));
+        let err = Config::parse(&s.replace("10000", "1000")).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "feeds.exclude_stale_ms must be at least feeds.stale_ms"
+        );
+    }
+
+    #[test]
+    pub fn demo() {
+        let s = r#"
+            assets = [
+                { name = "BTC", size_dp = 8, venues = ["kraken"] },
+                { name = "AVAX", size_dp = 2, venues = ["kraken"], groupings = [0.001, 0.01] },
+            ]
+            [demo]
+            depth = 20
+            assets = { btc = { price = 60000, tick = 0.5 } }
+        "#;
+        let config = Config::parse(s).unwrap();
+        assert_eq!(config.demo.depth, 20);
+        assert_eq!(config.demo.book_ms, 250);
+        assert_eq!(config.demo.asset(&config.assets[0]), (60_000.0, dec!(0.5)));
+        assert_eq!(config.demo.asset(&config.assets[1]), (100.0, dec!(0.001)));
+        let err = Config::parse(&s.replace("tick = 0.5", "tick = 0")).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "demo.assets.btc: price and tick must be positive"
+        );
+    }
+
+    #[test]
+    pub fn parse_spec() {
+        let asset = AssetConfig::parse_spec("avax 2 kraken coinbase=AVAX-USDT").unwrap();
+        assert_eq!(asset.name, "AVAX");
+        assert_eq!(asset.instrument, Instrument::new("AVAX", "USD"));
+        assert_eq!(asset.size_dp, 2);
+        assert_eq!(asset.venues, vec![Exchange::Kraken, Exchange::Gdax]);
+        assert_eq!(asset.tickers.get(&Exchange::Kraken).unwrap(), "AVAX/USD");
+        assert_eq!(asset.tickers.get(&Exchange::Gdax).unwrap(), "AVAX-USDT");
+        assert_eq!(asset.groupings, DEFAULT_GROUPINGS.to_vec());
+        assert!(asset.grouping.is_none());
+        assert!(AssetConfig::parse_spec("AVAX").is_err());
+        assert!(AssetConfig::parse_spec("AVAX ftx=AVAX/USD").is_err());
+    }
+
+    #[test]
+    pub fn restrict_venues() {
+        let mut config = Config::parse(DEFAULT_CONFIG).unwrap();
+        config
+            .restrict_venues(&["kraken".to_string(), "hyperliquid".to_string()])
+            .unwrap();
+        assert_eq!(
+            config.assets[0].venues,
+            vec![Exchange::Kraken, Exchange::Hyperliquid]
+        );
+        assert!(!config.assets[0].tickers.contains_key(&Exchange::Gdax));
+        assert_eq!(config.assets[config.default_asset].name, "SOL");
+
+        // The selected asset losing every venue is an error rather than a switch to another asset
+        let mut config = Config::parse(
+            r#"
+            default_asset = "ETH/BTC"
+            assets = [
+                { name = "BTC", size_dp = 8, venues = ["kraken", "hyperliquid"] },
+                { name = "ETH/BTC", size_dp = 4, venues = ["kraken", "coinbase"] },
+            ]
+        "#,
+        )
+        .unwrap();
+        let err = config
+            .restrict_venues(&["hyperliquid".to_string()])
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "--asset ETH/BTC does not trade on --exchanges hyperliquid"
+        );
+    }
+
+    #[test]
+    pub fn replay_assets() {
+        let mut config = Config::parse(DEFAULT_CONFIG).unwrap();
+        let sol = config.assets[config.default_asset].clone();
+        let recorded = BTreeMap::from([
+            (
+                "SOL".to_string(),
+                BTreeMap::from([
+                    ("kraken".to_string(), "SOL/USD".to_string()),
+                    ("hyperliquid".to_string(), "SOL".to_string()),
+                ]),
+            ),
+            (
+                "AVAX".to_string(),
+                BTreeMap::from([
+                    ("coinbase".to_string(), "AVAX-USD".to_string()),
+                    ("kraken".to_string(), "AVAX/USD".to_string()),
+                ]),
+            ),
+        ]);
+        let recorded_venues = BTreeMap::from([(
+            "AVAX".to_string(),
+            vec!["kraken".to_string(), "coinbase".to_string()],
+        )]);
+        config.replay_assets(&recorded, &recorded_venues).unwrap();
+        assert_eq!(config.assets.len(), 2);
+        let selected = &config.assets[config.default_asset];
+        assert_eq!(selected.name, "SOL");
+        // Older recordings fall back on the configured venue order, not alphabetical
+        assert_eq!(
+            selected.venues,
+            vec![Exchange::Kraken, Exchange::Hyperliquid]
+        );
+        assert_eq!(selected.groupings, sol.groupings);
+        let avax = config.assets.iter().find(|a| a.name == "AVAX").unwrap();
+        assert_eq!(avax.venues, vec![Exchange::Kraken, Exchange::Gdax]);
+        assert!(config
+            .replay_assets(&BTreeMap::new(), &BTreeMap::new())
+            .is_err());
+    }
+}
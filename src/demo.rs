@@ -0,0 +1,460 @@
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+use agg_ws::{
+    client::{Channel, ChannelType, Exchange},
+    trades::Trade,
+};
+use async_trait::async_trait;
+use chrono::Utc;
+use futures::future::{self, BoxFuture, FutureExt};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use rust_decimal::prelude::*;
+use tokio::{sync::mpsc, time};
+
+use crate::{
+    config::{exchange_name, AssetConfig, DemoConfig},
+    feed::{MarketData, Message, Response, VenueBook},
+    symbols::units,
+};
+
+// Trades kept and sent on each venue's tape
+const TAPE_LEN: usize = 50;
+// Typical order size in quote currency, scaled to each asset's price
+const ORDER_NOTIONAL: f64 = 5_000.0;
+
+/// Simulated exchanges for offline development, screenshots and load testing. Each asset's mid
+/// follows a random walk shared by its venues, each venue quoting around it with its own drifting
+/// offset so books overlap and now and then cross. Trades arrive at random at the configured
+/// average rate and take the venue's best bid or offer.
+pub struct Demo {
+    config: DemoConfig,
+    rng: StdRng,
+    assets: Vec<SimAsset>,
+    next_book: Instant,
+    /// Messages generated and waiting to be received
+    ready: VecDeque<Message>,
+    // Acknowledgements and answers to requests
+    requests_tx: mpsc::UnboundedSender<Message>,
+    requests_rx: mpsc::UnboundedReceiver<Message>,
+}
+
+struct SimAsset {
+    name: String,
+    mid: f64,
+    tick: Decimal,
+    size_dp: u32,
+    venues: Vec<SimVenue>,
+}
+
+struct SimVenue {
+    exchange: Exchange,
+    market: String,
+    /// Base units per unit of the market, prices and sizes are quoted in these
+    units: Decimal,
+    /// Distance of the venue's mid from the asset's in basis points
+    offset_bps: f64,
+    book: VenueBook,
+    tape: VecDeque<Trade>,
+    next_trade: Option<Instant>,
+    book_subscribed: bool,
+    tape_subscribed: bool,
+}
+
+impl SimVenue {
+    fn channel(&self, channel: ChannelType) -> Channel {
+        Channel {
+            exchange: self.exchange,
+            channel,
+            market: self.market.clone(),
+        }
+    }
+}
+
+impl Demo {
+    pub fn new(config: &DemoConfig) -> Demo {
+        let (requests_tx, requests_rx) = mpsc::unbounded_channel();
+        Demo {
+            config: config.clone(),
+            rng: match config.seed {
+                Some(seed) => StdRng::seed_from_u64(seed),
+                None => StdRng::from_entropy(),
+            },
+            assets: Vec::new(),
+            next_book: Instant::now(),
+            ready: VecDeque::new(),
+            requests_tx,
+            requests_rx,
+        }
+    }
+
+    fn venue(&self, channel: &Channel) -> Option<&SimVenue> {
+        self.assets
+            .iter()
+            .flat_map(|a| a.venues.iter())
+            .find(|v| v.exchange == channel.exchange && v.market == channel.market)
+    }
+
+    fn venue_mut(&mut self, channel: &Channel) -> Option<&mut SimVenue> {
+        self.assets
+            .iter_mut()
+            .flat_map(|a| a.venues.iter_mut())
+            .find(|v| v.exchange == channel.exchange && v.market == channel.market)
+    }
+
+    // Time until the next trade, exponentially distributed for a Poisson arrival rate
+    fn trade_wait(&mut self) -> Option<Duration> {
+        if self.config.trades_per_sec <= 0.0 {
+            return None;
+        }
+        let u: f64 = self.rng.gen_range(f64::EPSILON..1.0);
+        Some(Duration::from_secs_f64(
+            -u.ln() / self.config.trades_per_sec,
+        ))
+    }
+
+    // A standard normal draw
+    fn normal(&mut self) -> f64 {
+        let u: f64 = self.rng.gen_range(f64::EPSILON..1.0);
+        let v: f64 = self.rng.gen();
+        (-2.0 * u.ln()).sqrt() * (2.0 * std::f64::consts::PI * v).cos()
+    }
+
+    // Move every mid and venue offset on by one book interval and send the books
+    fn step(&mut self) {
+        let secs = self.config.book_ms as f64 / 1_000.0;
+        let vol = self.config.volatility_bps / 10_000.0 * secs.sqrt();
+        // Offsets revert towards the shared mid within a few seconds
+        let revert = (-secs / 3.0).exp();
+        let drift = self.config.spread_bps * 0.5 * (1.0 - revert * revert).sqrt();
+        for a in 0..self.assets.len() {
+            let z = self.normal();
+            self.assets[a].mid *= (vol * z).exp();
+            for v in 0..self.assets[a].venues.len() {
+                let z = self.normal();
+                let venue = &mut self.assets[a].venues[v];
+                venue.offset_bps = venue.offset_bps * revert + drift * z;
+                let book = self.book(a, v);
+                let venue = &mut self.assets[a].venues[v];
+                venue.book = book;
+                if venue.book_subscribed {
+                    let msg = message(
+                        venue.channel(ChannelType::Book),
+                        Response::Book(venue.book.clone()),
+                    );
+                    self.ready.push_back(msg);
+                }
+            }
+        }
+    }
+
+    // A full book around the venue's mid, levels thinning out a tick or two apart and growing
+    // with distance from the inside
+    fn book(&mut self, a: usize, v: usize) -> VenueBook {
+        let asset = &self.assets[a];
+        let venue = &asset.venues[v];
+        let mid = asset.mid * (1.0 + venue.offset_bps / 10_000.0);
+        let tick = asset.tick.to_f64().unwrap_or(0.01);
+        let half = mid * self.config.spread_bps / 20_000.0;
+        let bid = ((mid - half) / tick).floor() as i64;
+        let ask = (((mid + half) / tick).ceil() as i64).max(bid + 1);
+        let (tick, size_dp, base) = (asset.tick, asset.size_dp, ORDER_NOTIONAL / asset.mid);
+        let units = venue.units;
+        let mut book = VenueBook::default();
+        for (levels, start, dir) in [(&mut book.bids, bid, -1), (&mut book.asks, ask, 1)] {
+            let mut ticks = start;
+            for i in 0..self.config.depth {
+                let scale = (1.0 + i as f64 * 0.15) * self.rng.gen_range(0.2..1.8);
+                levels.insert(
+                    tick * Decimal::from(ticks) * units,
+                    size(base * scale, size_dp) / units,
+                );
+                ticks += dir * self.rng.gen_range(1..=2);
+                if ticks <= 0 {
+                    break;
+                }
+            }
+        }
+        book
+    }
+
+    // A trade taking the venue's best bid or offer
+    fn trade(&mut self, a: usize, v: usize, now: Instant) {
+        let buy = self.rng.gen_bool(0.5);
+        let u: f64 = self.rng.gen_range(f64::EPSILON..1.0);
+        let wait = self.trade_wait();
+        let asset = &self.assets[a];
+        let qty = size(-u.ln() * ORDER_NOTIONAL / asset.mid, asset.size_dp);
+        let venue = &mut self.assets[a].venues[v];
+        venue.next_trade = wait.map(|w| now + w);
+        let best = if buy {
+            venue.book.asks.keys().next()
+        } else {
+            venue.book.bids.keys().next_back()
+        };
+        let Some(price) = best.copied() else {
+            return;
+        };
+        // Venue tapes are in time order, so each trade is stamped after the last
+        let dt = match venue.tape.back() {
+            Some(last) => Utc::now().max(last.dt + chrono::Duration::microseconds(1)),
+            None => Utc::now(),
+        };
+        venue.tape.push_back(Trade {
+            size: (qty / venue.units).to_string(),
+            price: price.to_string(),
+            dt,
+            exchange: venue.exchange,
+        });
+        if venue.tape.len() > TAPE_LEN {
+            venue.tape.pop_front();
+        }
+        if venue.tape_subscribed {
+            let msg = message(
+                venue.channel(ChannelType::Tape),
+                Response::Tape(venue.tape.clone()),
+            );
+            self.ready.push_back(msg);
+        }
+    }
+
+    // The venue and time of the next trade across every asset
+    fn next_trade(&self) -> Option<(usize, usize, Instant)> {
+        self.assets
+            .iter()
+            .enumerate()
+            .flat_map(|(a, asset)| {
+                asset
+                    .venues
+                    .iter()
+                    .enumerate()
+                    .filter_map(move |(v, venue)| venue.next_trade.map(|t| (a, v, t)))
+            })
+            .min_by_key(|(_, _, t)| *t)
+    }
+
+    // Generate everything due by `now`
+    fn advance(&mut self, now: Instant) {
+        if self.next_book <= now {
+            self.step();
+            self.next_book = now + Duration::from_millis(self.config.book_ms);
+        }
+        while let Some((a, v, t)) = self.next_trade() {
+            if t > now {
+                break;
+            }
+            self.trade(a, v, now);
+        }
+    }
+
+    fn respond(&self, channel: Channel, resp: Response) {
+        let _ = self.requests_tx.send(message(channel, resp));
+    }
+}
+
+#[async_trait]
+impl MarketData for Demo {
+    fn subscribe(&self, channel: Channel) -> BoxFuture<'static, Result<(), String>> {
+        let result = match self.venue(&channel) {
+            Some(_) => {
+                self.respond(channel, Response::Subscribed);
+                Ok(())
+            }
+            None => Err(format!(
+                "no simulated market {} on {}",
+                channel.market,
+                exchange_name(channel.exchange)
+            )),
+        };
+        future::ready(result).boxed()
+    }
+
+    async fn unsubscribe(&self, channel: Channel) -> Result<(), String> {
+        self.respond(channel, Response::Unsubscribed);
+        Ok(())
+    }
+
+    async fn get_tape(&self, channel: Channel) -> Result<(), String> {
+        if let Some(venue) = self.venue(&channel).filter(|v| !v.tape.is_empty()) {
+            self.respond(channel, Response::Tape(venue.tape.clone()));
+        }
+        Ok(())
+    }
+
+    async fn get_book(&self, channel: Channel) -> Result<(), String> {
+        if let Some(venue) = self.venue(&channel) {
+            self.respond(channel, Response::Book(venue.book.clone()));
+        }
+        Ok(())
+    }
+
+    async fn recv(&mut self) -> Option<Message> {
+        loop {
+            if let Some(msg) = self.ready.pop_front() {
+                return Some(msg);
+            }
+            let due = match self.next_trade() {
+                Some((_, _, t)) => t.min(self.next_book),
+                None => self.next_book,
+            };
+            tokio::select! {
+                Some(msg) = self.requests_rx.recv() => {
+                    let subscribed = match msg.resp {
+                        Response::Subscribed => Some(true),
+                        Response::Unsubscribed => Some(false),
+                        _ => None,
+                    };
+                    let channel = msg.channel.clone();
+                    if let (Some(subscribed), Some(venue)) = (subscribed, self.venue_mut(&channel)) {
+                        match channel.channel {
+                            ChannelType::Book => venue.book_subscribed = subscribed,
+                            ChannelType::Tape => venue.tape_subscribed = subscribed,
+                        }
+                    }
+                    return Some(msg);
+                }
+                _ = time::sleep_until(due.into()) => self.advance(Instant::now()),
+            }
+        }
+    }
+
+    // Venues of an asset opened again are added to its existing simulation
+    fn add_asset(&mut self, asset: &AssetConfig) {
+        let a = match self.assets.iter().position(|a| a.name == asset.name) {
+            Some(a) => a,
+            None => {
+                let (mid, tick) = self.config.asset(asset);
+                self.assets.push(SimAsset {
+                    name: asset.name.clone(),
+                    mid,
+                    tick,
+                    size_dp: asset.size_dp,
+                    venues: Vec::new(),
+                });
+                self.assets.len() - 1
+            }
+        };
+        // Venues in config order so a seeded session repeats
+        for exchange in asset.venues.iter() {
+            let market = asset.tickers[exchange].clone();
+            let venues = &self.assets[a].venues;
+            if venues
+                .iter()
+                .any(|v| v.exchange == *exchange && v.market == market)
+            {
+                continue;
+            }
+            let next_trade = self.trade_wait().map(|w| Instant::now() + w);
+            self.assets[a].venues.push(SimVenue {
+                exchange: *exchange,
+                units: units(*exchange, &market),
+                market,
+                offset_bps: 0.0,
+                book: VenueBook::default(),
+                tape: VecDeque::new(),
+                next_trade,
+                book_subscribed: false,
+                tape_subscribed: false,
+            });
+            let v = self.assets[a].venues.len() - 1;
+            self.assets[a].venues[v].book = self.book(a, v);
+        }
+    }
+}
+
+fn message(channel: Channel, resp: Response) -> Message {
+    Message {
+        channel,
+        resp,
+        received_at: Utc::now(),
+    }
+}
+
+// A positive size rounded to the asset's size precision
+fn size(size: f64, dp: u32) -> Decimal {
+    let min = Decimal::new(1, dp);
+    Decimal::from_f64(size)
+        .map(|s| s.round_dp(dp))
+        .unwrap_or(min)
+        .max(min)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    fn demo() -> Demo {
+        let config = Config::parse(
+            r#"
+            assets = [{ name = "BTC", size_dp = 4, venues = ["kraken", "coinbase"] }]
+            [demo]
+            depth = 10
+            seed = 7
+        "#,
+        )
+        .unwrap();
+        let mut demo = Demo::new(&config.demo);
+        demo.add_asset(&config.assets[0]);
+        demo
+    }
+
+    fn is_tick(price: &Decimal, tick: Decimal) -> bool {
+        (price % tick).is_zero()
+    }
+
+    #[test]
+    pub fn simulates_venues() {
+        let mut demo = demo();
+        assert_eq!(demo.assets[0].tick, Decimal::new(1, 1));
+        for _ in 0..20 {
+            demo.step();
+        }
+        // Nothing is sent until subscribed
+        assert!(demo.ready.is_empty());
+        let mid = Decimal::from_f64(demo.assets[0].mid).unwrap();
+        for venue in demo.assets[0].venues.iter() {
+            let book = &venue.book;
+            assert_eq!((book.bids.len(), book.asks.len()), (10, 10));
+            let bid = book.bids.keys().next_back().unwrap();
+            let ask = book.asks.keys().next().unwrap();
+            assert!(bid < ask);
+            // Within a few spreads of the shared mid
+            assert!(((bid + ask) / Decimal::TWO - mid).abs() / mid < Decimal::new(1, 3));
+            assert!(book
+                .bids
+                .keys()
+                .chain(book.asks.keys())
+                .all(|p| is_tick(p, Decimal::new(1, 1))));
+            assert!(book
+                .bids
+                .values()
+                .chain(book.asks.values())
+                .all(|s| *s > Decimal::ZERO && s.scale() <= 4));
+        }
+
+        let kraken = demo.assets[0].venues[0].channel(ChannelType::Tape);
+        demo.venue_mut(&kraken).unwrap().tape_subscribed = true;
+        let now = Instant::now();
+        for _ in 0..5 {
+            demo.trade(0, 0, now);
+        }
+        let venue = &demo.assets[0].venues[0];
+        assert_eq!(venue.tape.len(), 5);
+        let best = |t: &Trade| {
+            let price = Decimal::from_str(&t.price).unwrap();
+            venue.book.bids.keys().next_back() == Some(&price)
+                || venue.book.asks.keys().next() == Some(&price)
+        };
+        assert!(venue.tape.iter().all(best));
+        assert!(venue
+            .tape
+            .iter()
+            .zip(venue.tape.iter().skip(1))
+            .all(|(a, b)| a.dt < b.dt));
+        assert!(venue.next_trade.is_some_and(|t| t > now));
+        assert_eq!(demo.ready.len(), 5);
+    }
+}
@@ -0,0 +1,1486 @@
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use agg_ws::{
+    client::{Channel, ChannelType, Exchange},
+    trades::Trade,
+};
+use chrono::{DateTime, Utc};
+use futures::{future::BoxFuture, stream::FuturesUnordered, FutureExt, StreamExt};
+use rust_decimal::Decimal;
+use tokio::{
+    sync::{mpsc, watch},
+    time::{self, MissedTickBehavior},
+};
+
+use crate::{
+    arb::{detect, Cross, CrossLog},
+    bbo::Bbo,
+    book::{apply_delta, changed_prices, Levels},
+    config::{AssetConfig, Config, DisplayConfig, FeedConfig, SubscriptionConfig},
+    feed::{MarketData, Message, Response, VenueBook},
+    record::Recorder,
+    replay::{ReplayControl, ReplayStatus},
+    subscriptions::{RetryPolicy, SubEntry, SubState, Subscriptions},
+    sweep::{SweepOrder, SweepReport},
+    symbols::units,
+    tape::{classify, Mids, TapeTrade},
+    AggBook, AggExchange,
+};
+
+// How soon queued subscriptions are requested, independent of the refresh interval
+const SUB_INTERVAL: Duration = Duration::from_millis(50);
+
+// Longest gap between polls of a quiet channel, in poll intervals
+const MAX_POLL_BACKOFF: u32 = 4;
+
+/// Requests from the UI to the ingestion task.
+#[derive(Debug)]
+pub enum Command {
+    /// The selected asset, exchange or book grouping changed, `None` when no exchange is selected
+    /// or the book is not grouped
+    View {
+        asset: String,
+        exchange: Option<AggExchange>,
+        grouping: Option<Decimal>,
+    },
+    AddAsset(AssetConfig),
+    RemoveAsset(String),
+    /// Retry every channel of the selected asset that is not subscribed
+    Resubscribe,
+    /// Price an order against the selected asset's book on every publish, or stop
+    Sweep(Option<SweepOrder>),
+    /// Playback control, ignored unless replaying a recording
+    Replay(ReplayControl),
+}
+
+/// Market data for the selected asset and exchange as of the last publish. Snapshots are never
+/// modified once sent, the UI draws whichever one is latest.
+#[derive(Debug, Clone, Default)]
+pub struct Snapshot {
+    pub asset: String,
+    pub trades: Vec<TapeTrade>,
+    pub book: AggBook,
+    pub subscriptions: Vec<(Channel, SubEntry)>,
+    pub dropped_trades: usize,
+    /// Top of book of each venue of the selected asset
+    pub bbo: Vec<Bbo>,
+    /// Crossed venues of the selected asset and how long each has been crossed
+    pub crosses: Vec<(Cross, Duration)>,
+    /// Venues of the selected asset whose book has stopped changing
+    pub stale: Vec<Exchange>,
+    /// Stale venues left out of the aggregated book
+    pub excluded: Vec<Exchange>,
+    /// Cost to fill the requested order, if any
+    pub sweep: Option<SweepReport>,
+    /// Playback position when replaying a recording
+    pub replay: Option<ReplayStatus>,
+}
+
+// A subscribe request and its outcome, with timeouts reported as errors
+type SubRequest = BoxFuture<'static, (Channel, Result<(), String>)>;
+
+/// Owns the market data source and all market data state. Runs in its own task so that message
+/// bursts and aggregation never hold up drawing or keyboard input.
+pub struct Engine {
+    pub source: Box<dyn MarketData>,
+    pub subscriptions: Subscriptions,
+    /// Subscribe requests waiting on the source, polled alongside everything else so a stalled
+    /// connect holds up nothing but its own channel
+    pub in_flight: FuturesUnordered<SubRequest>,
+    pub sub_config: SubscriptionConfig,
+    pub display: DisplayConfig,
+    pub feeds: FeedConfig,
+    pub assets: Vec<String>,
+    pub tickers: HashMap<String, HashMap<Exchange, String>>,
+    /// Venues of each asset in config order
+    pub venues: HashMap<String, Vec<Exchange>>,
+    /// Selected asset and exchange, mirrored from the UI
+    pub asset: String,
+    pub exchange: Option<AggExchange>,
+    pub grouping: Option<Decimal>,
+    pub viewed_at: HashMap<String, Instant>,
+    pub tapes: HashMap<Channel, VecDeque<TapeTrade>>,
+    /// Trades received on each tape at its newest exchange timestamp
+    pub tape_seen: HashMap<Channel, Vec<Trade>>,
+    /// Trades dropped for failing validation
+    pub dropped_trades: usize,
+    pub books: HashMap<Channel, VenueBook>,
+    /// Recent mids of each venue book, to classify trades by the book they traded into
+    pub mids: HashMap<Channel, Mids>,
+    /// Aggregated book of each asset, updated level by level as venue books arrive
+    pub agg_books: HashMap<String, AggBook>,
+    /// Stale book channels dropped from the aggregated books until they change again
+    pub excluded: HashSet<Channel>,
+    pub trades: Vec<TapeTrade>,
+    pub book: AggBook,
+    pub fees_bps: HashMap<Exchange, Decimal>,
+    /// Crossed venues across every open asset as of the last publish
+    pub crosses: Vec<Cross>,
+    pub cross_log: CrossLog,
+    /// Order priced against the selected asset's book, cleared when the asset changes
+    pub sweep: Option<SweepOrder>,
+    /// Writes every response received when recording
+    pub recorder: Option<Recorder>,
+    /// Poll every channel on each interval instead of backing off from channels that are quiet
+    pub fixed_poll: bool,
+    pub poll_interval: Duration,
+    /// When each channel was last polled
+    pub last_poll: HashMap<Channel, Instant>,
+    /// When each channel's data last changed. Polled or repeated data that is identical to what
+    /// is held does not count, so a feed that quietly stops goes stale even while polled.
+    pub last_update: HashMap<Channel, Instant>,
+    /// Set when the published snapshot is out of date
+    pub dirty: bool,
+}
+
+impl Engine {
+    pub fn new(config: &Config, source: Box<dyn MarketData>) -> Engine {
+        let mut engine = Engine {
+            source,
+            subscriptions: Subscriptions::new(RetryPolicy::from(&config.subscriptions)),
+            in_flight: FuturesUnordered::new(),
+            sub_config: config.subscriptions.clone(),
+            display: config.display.clone(),
+            feeds: config.feeds.clone(),
+            assets: Vec::with_capacity(config.assets.len()),
+            tickers: HashMap::new(),
+            venues: HashMap::new(),
+            asset: config.assets[config.default_asset].name.clone(),
+            exchange: Some(AggExchange::Aggregate),
+            grouping: {
+                let asset = &config.assets[config.default_asset];
+                asset.grouping.map(|i| asset.groupings[i])
+            },
+            viewed_at: HashMap::new(),
+            tapes: HashMap::new(),
+            tape_seen: HashMap::new(),
+            dropped_trades: 0,
+            books: HashMap::new(),
+            mids: HashMap::new(),
+            agg_books: HashMap::new(),
+            excluded: HashSet::new(),
+            trades: Vec::with_capacity(50),
+            book: AggBook::new(),
+            fees_bps: config.arbitrage.fees_bps.clone(),
+            crosses: Vec::new(),
+            cross_log: CrossLog::default(),
+            sweep: None,
+            recorder: None,
+            fixed_poll: false,
+            poll_interval: Duration::from_millis(350),
+            last_poll: HashMap::new(),
+            last_update: HashMap::new(),
+            dirty: true,
+        };
+        for asset in config.assets.iter() {
+            engine.add_asset(asset);
+        }
+        engine
+    }
+
+    /// Applies UI commands and client messages until the UI goes away, publishing a snapshot
+    /// whenever data changed, at most once per `frame`. Crosses still open are logged on exit.
+    pub async fn run(
+        mut self,
+        mut commands: mpsc::UnboundedReceiver<Command>,
+        snapshots: watch::Sender<Arc<Snapshot>>,
+        interval: Duration,
+        frame: Duration,
+    ) {
+        let mut interval = time::interval(interval);
+        let mut sub_interval = time::interval(SUB_INTERVAL);
+        let mut publish = time::interval(frame);
+        publish.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+        loop {
+            tokio::select! {
+                _ = interval.tick() => self.manage_state().await,
+                _ = sub_interval.tick(), if self.has_due_subs() => self.subscribe_channels(),
+                Some((channel, result)) = self.in_flight.next(), if !self.in_flight.is_empty() => {
+                    self.subscribe_result(&channel, result)
+                },
+                _ = publish.tick(), if self.dirty => {
+                    self.update_state();
+                    // Only fails once every receiver is gone, e.g. when running headless
+                    let _ = snapshots.send(Arc::new(self.snapshot()));
+                    self.dirty = false;
+                },
+                cmd = commands.recv() => match cmd {
+                    Some(cmd) => self.handle_command(cmd).await,
+                    None => break,
+                },
+                msg = self.source.recv() => {
+                    if let Some(msg) = msg {
+                        self.response_handler(msg);
+                    }
+                }
+            };
+        }
+        self.cross_log.close(self.source.now());
+    }
+
+    pub async fn handle_command(&mut self, cmd: Command) {
+        match cmd {
+            Command::View {
+                asset,
+                exchange,
+                grouping,
+            } => {
+                self.grouping = grouping;
+                self.view(asset, exchange).await
+            }
+            Command::AddAsset(asset) => self.add_asset(&asset),
+            Command::RemoveAsset(asset) => self.remove_asset(&asset).await,
+            Command::Resubscribe => self.resubscribe(),
+            Command::Sweep(order) => self.sweep = order,
+            Command::Replay(control) => self.control_replay(control),
+        }
+        self.dirty = true;
+    }
+
+    // Jumps and steps apply their messages at once, after clearing market data when going back
+    fn control_replay(&mut self, control: ReplayControl) {
+        let Some(seek) = self.source.replay_control(control) else {
+            return;
+        };
+        if seek.reset {
+            self.clear_market_data();
+        }
+        for msg in seek.messages {
+            self.response_handler(msg);
+        }
+    }
+
+    fn clear_market_data(&mut self) {
+        self.tapes.clear();
+        self.tape_seen.clear();
+        self.dropped_trades = 0;
+        self.books.clear();
+        for book in self.agg_books.values_mut() {
+            *book = AggBook::new();
+        }
+        self.excluded.clear();
+        self.last_update.clear();
+        self.crosses.clear();
+        self.cross_log.clear();
+    }
+
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            asset: self.asset.clone(),
+            trades: self.trades.clone(),
+            book: self.book.clone(),
+            subscriptions: self
+                .subscriptions
+                .iter()
+                .map(|(c, e)| (c.clone(), e.clone()))
+                .collect(),
+            dropped_trades: self.dropped_trades,
+            bbo: self.bbos(&self.asset),
+            crosses: self
+                .crosses
+                .iter()
+                .filter(|c| c.asset == self.asset)
+                .map(|c| (c.clone(), self.cross_log.duration(c, self.source.now())))
+                .collect(),
+            stale: self.venues_where(&self.asset, |c| self.is_stale(c)),
+            excluded: self.venues_where(&self.asset, |c| self.excluded.contains(c)),
+            sweep: self.sweep_report(),
+            replay: self.source.replay_status(),
+        }
+    }
+
+    // The order is walked through the full aggregated book rather than the published levels, so
+    // neither grouping nor display depth affect the cost
+    fn sweep_report(&self) -> Option<SweepReport> {
+        let order = self.sweep.as_ref()?;
+        let book = self.agg_books.get(&self.asset)?;
+        let mut venues = self.venues_where(&self.asset, |_| true);
+        venues.sort_by_key(|ex| AggExchange::Exchange(*ex).as_display());
+        Some(SweepReport::new(book, order, &venues))
+    }
+
+    // Venues of an asset whose book channel matches
+    fn venues_where(&self, asset: &str, f: impl Fn(&Channel) -> bool) -> Vec<Exchange> {
+        self.get_channels(asset)
+            .iter()
+            .filter(|c| matches!(c.channel, ChannelType::Book) && f(c))
+            .map(|c| c.exchange)
+            .collect()
+    }
+
+    // A channel is stale once its data has not changed for the configured time. Channels that
+    // have not sent anything yet are pending rather than stale.
+    fn is_stale(&self, channel: &Channel) -> bool {
+        let now = self.source.now();
+        self.last_update.get(channel).is_some_and(|t| {
+            now.saturating_duration_since(*t) >= Duration::from_millis(self.feeds.stale_ms)
+        })
+    }
+
+    // Venue order is by name so rows stay put as books arrive
+    pub fn bbos(&self, asset: &str) -> Vec<Bbo> {
+        let now = self.source.now();
+        let mut bbos: Vec<Bbo> = self
+            .get_channels(asset)
+            .into_iter()
+            .filter(|c| matches!(c.channel, ChannelType::Book))
+            .map(|c| match self.books.get(&c) {
+                Some(book) => Bbo::new(
+                    c.exchange,
+                    &book.bids,
+                    &book.asks,
+                    self.last_update
+                        .get(&c)
+                        .map(|t| now.saturating_duration_since(*t)),
+                ),
+                None => Bbo::new(c.exchange, &Levels::new(), &Levels::new(), None),
+            })
+            .collect();
+        bbos.sort_by_key(|b| AggExchange::Exchange(b.exchange).as_display());
+        bbos
+    }
+
+    pub fn add_asset(&mut self, asset: &AssetConfig) {
+        if !self.assets.contains(&asset.name) {
+            self.assets.push(asset.name.clone());
+        }
+        self.tickers
+            .insert(asset.name.clone(), asset.tickers.clone());
+        self.venues.insert(asset.name.clone(), asset.venues.clone());
+        self.source.add_asset(asset);
+        // Venue books may already be held for another asset on the same markets
+        let mut agg_book = AggBook::new();
+        for channel in self.get_channels(&asset.name) {
+            if let Some(book) = self.books.get(&channel) {
+                agg_book.merge(channel.exchange, &book.bids, &book.asks);
+            }
+        }
+        self.agg_books.insert(asset.name.clone(), agg_book);
+        if let Some(recorder) = &self.recorder {
+            recorder.set_assets(&self.tickers, &self.venues);
+        }
+    }
+
+    pub async fn remove_asset(&mut self, asset: &str) {
+        if !self.assets.iter().any(|a| a == asset) {
+            return;
+        }
+        self.unsubscribe_asset(asset).await;
+        self.assets.retain(|a| a != asset);
+        self.tickers.remove(asset);
+        self.venues.remove(asset);
+        self.agg_books.remove(asset);
+        self.viewed_at.remove(asset);
+        if let Some(recorder) = &self.recorder {
+            recorder.set_assets(&self.tickers, &self.venues);
+        }
+    }
+
+    pub async fn view(&mut self, asset: String, exchange: Option<AggExchange>) {
+        if asset != self.asset {
+            // Stamp the asset being left so the subscription policy knows when it was last viewed
+            self.viewed_at.insert(self.asset.clone(), Instant::now());
+            self.asset = asset;
+            self.sweep = None;
+        }
+        self.exchange = exchange;
+        self.queue_subs().await;
+    }
+
+    pub fn response_handler(&mut self, msg: Message) {
+        tracing::info!("Response handler {:?}", msg.channel);
+        if let Some(recorder) = &self.recorder {
+            recorder.record(&msg);
+        }
+        match msg.resp {
+            Response::Subscribed => self.handle_subscribed(msg.channel),
+            Response::Unsubscribed => self.handle_unsubscribed(msg.channel),
+            Response::Tape(t) => self.handle_tape(msg.channel, t, msg.received_at),
+            Response::Book(b) => self.handle_book(msg.channel, b, msg.received_at),
+            Response::Last => {}
+        }
+    }
+
+    pub fn handle_subscribed(&mut self, channel: Channel) {
+        self.subscriptions.subscribed(&channel);
+        self.dirty = true;
+    }
+
+    // A channel opened again before its unsubscribe was acknowledged keeps its data
+    pub fn handle_unsubscribed(&mut self, channel: Channel) {
+        tracing::info!("Unsubscribed from {:?}", channel);
+        if !self.subscriptions.unsubscribed(&channel) {
+            tracing::info!("Channel {:?} was queued again, keeping it", channel);
+            return;
+        }
+        self.tapes.remove(&channel);
+        self.tape_seen.remove(&channel);
+        self.excluded.remove(&channel);
+        self.mids.remove(&channel);
+        if let Some(book) = self.books.remove(&channel) {
+            let (bids, asks) = prices(&book);
+            self.update_agg_books(&channel, &bids, &asks);
+        }
+        self.last_update.remove(&channel);
+        self.last_poll.remove(&channel);
+        self.dirty = true;
+    }
+
+    // Data still in flight for channels being unsubscribed is dropped
+    fn is_unsubscribing(&self, channel: &Channel) -> bool {
+        self.subscriptions.state(channel) == Some(SubState::Unsubscribing)
+    }
+
+    // The feed sends its recent trades each time, so only trades not seen before are parsed:
+    // those after the newest timestamp seen, and those at it beyond the ones already received, as
+    // several fills can share a timestamp. Records that fail validation are logged, counted and
+    // dropped.
+    pub fn handle_tape(
+        &mut self,
+        channel: Channel,
+        tape: VecDeque<Trade>,
+        received_at: DateTime<Utc>,
+    ) {
+        if self.is_unsubscribing(&channel) {
+            return;
+        }
+        tracing::debug!("Tape: {:?}", tape);
+        let mut seen = self.tape_seen.get(&channel).cloned();
+        let mids = self
+            .mids
+            .get(&Channel {
+                exchange: channel.exchange,
+                channel: ChannelType::Book,
+                market: channel.market.clone(),
+            })
+            .copied()
+            .unwrap_or_default();
+        let mut fresh: Vec<&Trade> = Vec::new();
+        for trade in tape.iter() {
+            let Some(seen) = seen.as_mut().filter(|s| !s.is_empty()) else {
+                fresh.push(trade);
+                continue;
+            };
+            let newest = seen[0].dt;
+            if trade.dt > newest {
+                fresh.push(trade);
+            } else if trade.dt == newest {
+                match seen.iter().position(|s| same_trade(s, trade)) {
+                    Some(i) => {
+                        seen.swap_remove(i);
+                    }
+                    None => fresh.push(trade),
+                }
+            }
+        }
+        // The tape has not moved since it was last received
+        if fresh.is_empty() && seen.is_some() {
+            return;
+        }
+        let units = units(channel.exchange, &channel.market);
+        let stored = self.tapes.entry(channel.clone()).or_default();
+        for trade in fresh {
+            match TapeTrade::parse(trade, received_at) {
+                Ok(t) => {
+                    let mut t = t.per_unit(units);
+                    let prev = stored.back().map(|p| (p.price, p.side));
+                    t.side = classify(t.price, mids.at(t.dt), prev);
+                    stored.push_back(t);
+                }
+                Err(e) => {
+                    tracing::warn!("Dropped trade from {:?}: {}", channel, e);
+                    self.dropped_trades += 1;
+                }
+            }
+        }
+        while stored.len() > tape.len().max(self.display.tape_depth) {
+            stored.pop_front();
+        }
+        if let Some(last) = tape.back() {
+            let newest: Vec<Trade> = tape.iter().filter(|t| t.dt == last.dt).cloned().collect();
+            self.tape_seen.insert(channel.clone(), newest);
+        }
+        self.last_update.insert(channel, self.source.now());
+        self.dirty = true;
+    }
+
+    pub fn handle_book(&mut self, channel: Channel, book: VenueBook, received_at: DateTime<Utc>) {
+        if self.is_unsubscribing(&channel) {
+            return;
+        }
+        let book = book.per_unit(units(channel.exchange, &channel.market));
+        let (bids, asks) = match self.books.get(&channel) {
+            Some(prev) => (
+                changed_prices(&prev.bids, &book.bids),
+                changed_prices(&prev.asks, &book.asks),
+            ),
+            None => prices(&book),
+        };
+        // A polled or repeated book identical to the one held is not an update
+        if self.books.contains_key(&channel) && bids.is_empty() && asks.is_empty() {
+            return;
+        }
+        self.last_update.insert(channel.clone(), self.source.now());
+        self.mids
+            .entry(channel.clone())
+            .or_default()
+            .update(received_at, mid(&book));
+        if self.excluded.remove(&channel) {
+            // Its levels were taken out of the aggregate when it was excluded
+            tracing::info!("Book {:?} is updating again", channel);
+            let (bids, asks) = prices(&book);
+            self.books.insert(channel.clone(), book);
+            self.update_agg_books(&channel, &bids, &asks);
+        } else {
+            self.books.insert(channel.clone(), book);
+            self.update_agg_books(&channel, &bids, &asks);
+        }
+        self.dirty = true;
+    }
+
+    // Take books that have not changed for the exclusion threshold out of the aggregated books
+    fn exclude_stale_books(&mut self) {
+        let Some(ms) = self.feeds.exclude_stale_ms else {
+            return;
+        };
+        let threshold = Duration::from_millis(ms);
+        let now = self.source.now();
+        let stale: Vec<Channel> = self
+            .books
+            .keys()
+            .filter(|c| !self.excluded.contains(*c))
+            .filter(|c| {
+                self.last_update
+                    .get(*c)
+                    .is_some_and(|t| now.saturating_duration_since(*t) >= threshold)
+            })
+            .cloned()
+            .collect();
+        for channel in stale {
+            tracing::warn!("Book {:?} unchanged for {}ms, excluding it", channel, ms);
+            self.excluded.insert(channel.clone());
+            let (bids, asks) = prices(&self.books[&channel]);
+            self.update_agg_books(&channel, &bids, &asks);
+        }
+    }
+
+    // Re-aggregate the prices that changed in a venue's book, for every asset built on the channel.
+    // Only those levels are merged across venues. Excluded books count as empty.
+    fn update_agg_books(&mut self, channel: &Channel, bids: &[Decimal], asks: &[Decimal]) {
+        let assets: Vec<String> = self
+            .assets
+            .iter()
+            .filter(|a| self.get_channels(a).contains(channel))
+            .cloned()
+            .collect();
+        for asset in assets {
+            let venues: Vec<(Exchange, &VenueBook)> = self
+                .get_channels(&asset)
+                .iter()
+                .filter(|c| !self.excluded.contains(*c))
+                .filter_map(|c| self.books.get(c).map(|b| (c.exchange, b)))
+                .collect();
+            let venue_bids: Vec<(Exchange, &Levels)> =
+                venues.iter().map(|(e, b)| (*e, &b.bids)).collect();
+            let venue_asks: Vec<(Exchange, &Levels)> =
+                venues.iter().map(|(e, b)| (*e, &b.asks)).collect();
+            let agg_book = self.agg_books.entry(asset).or_default();
+            apply_delta(&mut agg_book.bids, bids, &venue_bids);
+            apply_delta(&mut agg_book.asks, asks, &venue_asks);
+        }
+    }
+
+    // Channels in the asset's configured venue order, so levels list their venues in the same
+    // order whichever book arrived first
+    fn get_channels(&self, asset: &str) -> Vec<Channel> {
+        let mut channels = Vec::new();
+        let tickers = self.tickers.get(asset).unwrap();
+        for exchange in self.venues.get(asset).unwrap().iter() {
+            let channel = Channel {
+                exchange: *exchange,
+                channel: ChannelType::Tape,
+                market: tickers[exchange].clone(),
+            };
+            channels.push(channel);
+            let channel = Channel {
+                exchange: *exchange,
+                channel: ChannelType::Book,
+                market: tickers[exchange].clone(),
+            };
+            channels.push(channel);
+        }
+        channels
+    }
+
+    pub async fn manage_state(&mut self) {
+        self.expire_subs().await;
+        self.exclude_stale_books();
+        self.poll_data().await;
+        // Publish at least once per interval so ages and retry timers stay current
+        self.dirty = true;
+    }
+
+    // Due channels are only taken while there is room for another request
+    pub fn has_due_subs(&self) -> bool {
+        self.in_flight.len() < self.sub_config.max_parallel
+            && self.subscriptions.has_due(retry_clock())
+    }
+
+    // Send a request for every due channel, up to the configured parallelism. Each request is
+    // bounded by the request timeout and its outcome is handled when it completes, so nothing is
+    // awaited here.
+    pub fn subscribe_channels(&mut self) {
+        let now = retry_clock();
+        let timeout = Duration::from_millis(self.sub_config.request_timeout_ms);
+        while self.in_flight.len() < self.sub_config.max_parallel {
+            let Some(channel) = self.subscriptions.next_due(now) else {
+                break;
+            };
+            tracing::info!("Subscribing to {:?}", channel);
+            let request = time::timeout(timeout, self.source.subscribe(channel.clone()));
+            self.in_flight.push(
+                async move {
+                    let result = match request.await {
+                        Ok(result) => result,
+                        Err(_) => Err("request timed out".to_string()),
+                    };
+                    (channel, result)
+                }
+                .boxed(),
+            );
+        }
+        self.dirty = true;
+    }
+
+    // Successful requests wait for the Subscribed acknowledgement, failures are retried
+    pub fn subscribe_result(&mut self, channel: &Channel, result: Result<(), String>) {
+        if let Err(error) = result {
+            tracing::error!("Sub error {:?}: {}", channel, error);
+            self.subscriptions
+                .request_failed(channel, error, retry_clock());
+            self.dirty = true;
+        }
+    }
+
+    // Assets whose channels stay subscribed: the selected asset plus the most recently viewed
+    fn assets_to_keep(&self) -> HashSet<String> {
+        let now = Instant::now();
+        let idle_timeout = self.sub_config.idle_timeout_secs.map(Duration::from_secs);
+        let mut recent: Vec<(&String, &Instant)> = self
+            .viewed_at
+            .iter()
+            .filter(|(a, _)| **a != self.asset)
+            .filter(|(_, t)| match idle_timeout {
+                Some(d) => now.duration_since(**t) < d,
+                None => true,
+            })
+            .collect();
+        recent.sort_by(|a, b| b.1.cmp(a.1));
+        let mut keep: HashSet<String> = recent
+            .into_iter()
+            .take(self.sub_config.keep_recent)
+            .map(|(a, _)| a.clone())
+            .collect();
+        keep.insert(self.asset.clone());
+        keep
+    }
+
+    async fn expire_subs(&mut self) {
+        let keep = self.assets_to_keep();
+        let expired: Vec<String> = self
+            .assets
+            .iter()
+            .filter(|a| !keep.contains(*a))
+            .cloned()
+            .collect();
+        for asset in expired.iter() {
+            self.unsubscribe_asset(asset).await;
+        }
+        // Channels acknowledged after their asset was closed belong to no asset
+        let open: HashSet<Channel> = self
+            .assets
+            .iter()
+            .flat_map(|a| self.get_channels(a))
+            .collect();
+        let orphans: Vec<Channel> = self
+            .subscriptions
+            .iter()
+            .filter(|(c, _)| !open.contains(*c))
+            .map(|(c, _)| c.clone())
+            .collect();
+        self.unsubscribe_channels(orphans).await;
+    }
+
+    // Channels shared with an asset that stays subscribed, e.g. BTC and BTC/USD on the same
+    // markets, are left alone
+    async fn unsubscribe_asset(&mut self, asset: &str) {
+        let shared: HashSet<Channel> = self
+            .assets_to_keep()
+            .iter()
+            .filter(|a| *a != asset && self.tickers.contains_key(*a))
+            .flat_map(|a| self.get_channels(a))
+            .collect();
+        let channels = self
+            .get_channels(asset)
+            .into_iter()
+            .filter(|c| !shared.contains(c))
+            .collect();
+        self.unsubscribe_channels(channels).await;
+    }
+
+    async fn unsubscribe_channels(&mut self, channels: Vec<Channel>) {
+        for channel in channels {
+            // Channels not yet requested are simply dropped from the queue
+            if !self.subscriptions.dequeue(&channel) {
+                continue;
+            }
+            tracing::info!("Unsubscribing from {:?}", channel);
+            match self.source.unsubscribe(channel.clone()).await {
+                Ok(()) => self.subscriptions.unsubscribing(&channel),
+                Err(e) => tracing::error!("Unsub error: {}", e),
+            }
+        }
+    }
+
+    // Retry every channel of the selected asset that is not subscribed, resetting the backoff
+    pub fn resubscribe(&mut self) {
+        for channel in self.get_channels(&self.asset) {
+            if self.subscriptions.resubscribe(&channel) {
+                tracing::info!("Resubscribing to {:?}", channel);
+            }
+        }
+    }
+
+    pub async fn queue_subs(&mut self) {
+        // Get channels and send to client to subscirbe
+        let channels = self.get_channels(&self.asset);
+        tracing::debug!("Channels: {:?}", channels);
+        for channel in channels.iter() {
+            // Skip if already queued, requested or subscribed
+            if self.subscriptions.queue(channel) {
+                tracing::info!("Inserting channel into sub queue: {:?}", channel);
+            } else {
+                tracing::debug!("Channel {:?} already subbed.", channel);
+            }
+        }
+    }
+
+    // Channels of the selected asset feeding the selected exchange view
+    fn view_channels(&self) -> Vec<Channel> {
+        match self.exchange {
+            Some(AggExchange::Aggregate) => self.get_channels(&self.asset),
+            Some(AggExchange::Exchange(ex)) => self
+                .get_channels(&self.asset)
+                .into_iter()
+                .filter(|c| c.exchange == ex)
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+
+    // Poll data for asset and aggregate if needed
+    pub async fn poll_data(&mut self) {
+        let channels = self.view_channels();
+        let now = self.source.now();
+        let due: Vec<Channel> = channels
+            .into_iter()
+            .filter(|c| self.needs_poll(c, now))
+            .collect();
+        tracing::info!("Polling {} tapes and books.", due.len());
+        for channel in due.iter() {
+            self.last_poll.insert(channel.clone(), now);
+            let result = match channel.channel {
+                ChannelType::Tape => self.source.get_tape(channel.clone()).await,
+                ChannelType::Book => self.source.get_book(channel.clone()).await,
+            };
+            if let Err(e) = result {
+                tracing::error!("Poll error {:?}: {}", channel, e);
+            }
+        }
+    }
+
+    // Adaptive polling: a channel is polled each interval while its data keeps changing. Once it
+    // has been quiet for a while it is polled less often, up to MAX_POLL_BACKOFF intervals apart,
+    // so quiet markets cost fewer requests. Data a source sends unasked counts as a change too.
+    fn needs_poll(&self, channel: &Channel, now: Instant) -> bool {
+        if self.fixed_poll {
+            return true;
+        }
+        let Some(polled) = self.last_poll.get(channel) else {
+            return true;
+        };
+        let quiet = self
+            .last_update
+            .get(channel)
+            .map_or(Duration::ZERO, |t| now.saturating_duration_since(*t));
+        let gap = (quiet / 2).clamp(self.poll_interval, self.poll_interval * MAX_POLL_BACKOFF);
+        // Half an interval of slack so a tick landing slightly early still polls
+        now.saturating_duration_since(*polled) + self.poll_interval / 2 >= gap
+    }
+
+    // Update the Book and Trades state based on the selected Asset and Exchange
+    pub fn update_state(&mut self) {
+        self.update_crosses();
+        self.trades = Vec::with_capacity(50);
+        self.book = AggBook::new();
+        match self.exchange.clone() {
+            // Aggregated Exchange is selected - Merge all tapes and book for Asset
+            Some(AggExchange::Aggregate) => {
+                let channels = self.get_channels(&self.asset);
+                self.update_state_agg_trades(&channels);
+                // The aggregated book is kept up to date as venue books arrive
+                self.book = self
+                    .agg_books
+                    .get(&self.asset)
+                    .map(|b| self.view_book(b))
+                    .unwrap_or_default();
+            }
+            // Copy the book and tape for the given Exchange and Asset
+            Some(AggExchange::Exchange(ex)) => {
+                let ticker = self
+                    .tickers
+                    .get(&self.asset)
+                    .unwrap()
+                    .get(&ex)
+                    .unwrap()
+                    .clone();
+                // Copy the tape
+                self.update_state_trades(&ex, &ticker);
+                // Get book for exchange and convert into AggBook structure
+                let channel = Channel {
+                    exchange: ex,
+                    channel: ChannelType::Book,
+                    market: ticker,
+                };
+                if let Some(book) = self.books.get(&channel) {
+                    self.book = self.view_book(&AggBook::from_exchange(ex, &book.bids, &book.asks));
+                }
+            }
+            None => {}
+        }
+    }
+
+    // Check every open asset for one venue's bid above another's ask. Stale books are skipped as
+    // a frozen quote would show a cross that no longer exists.
+    fn update_crosses(&mut self) {
+        let mut crosses = Vec::new();
+        for asset in self.assets.iter() {
+            let venues: Vec<(Exchange, &Levels, &Levels)> = self
+                .get_channels(asset)
+                .iter()
+                .filter(|c| matches!(c.channel, ChannelType::Book) && !self.is_stale(c))
+                .filter_map(|c| self.books.get(c).map(|b| (c.exchange, &b.bids, &b.asks)))
+                .collect();
+            crosses.extend(detect(asset, &venues, &self.fees_bps));
+        }
+        self.cross_log.update(&crosses, self.source.now());
+        self.crosses = crosses;
+    }
+
+    // Only the levels that can be displayed are published, after grouping
+    fn view_book(&self, book: &AggBook) -> AggBook {
+        match self.grouping {
+            Some(grouping) => book.grouped(grouping, self.display.book_depth),
+            None => book.top(self.display.book_depth),
+        }
+    }
+
+    pub fn update_state_agg_trades(&mut self, channels: &[Channel]) {
+        let mut trades: Vec<TapeTrade> = Vec::with_capacity(100 * channels.len());
+        for channel in channels.iter() {
+            if let Some(trades_vd) = self.tapes.get(channel).cloned() {
+                trades.append(&mut trades_vd.into());
+            }
+        }
+        trades.sort_by_key(|t| t.dt);
+        self.trades = trades
+            .into_iter()
+            .rev()
+            .take(self.display.tape_depth)
+            .collect();
+    }
+
+    pub fn update_state_trades(&mut self, exchange: &Exchange, ticker: &str) {
+        let channel = Channel {
+            exchange: *exchange,
+            channel: ChannelType::Tape,
+            market: ticker.to_string(),
+        };
+        if let Some(trades_vd) = self.tapes.get(&channel).cloned() {
+            let trades_v: Vec<TapeTrade> = trades_vd.into();
+            self.trades = trades_v
+                .into_iter()
+                .rev()
+                .take(self.display.tape_depth)
+                .collect();
+        } else {
+            self.trades = Vec::with_capacity(50);
+        }
+    }
+}
+
+// Retry timers read the runtime's clock, which is the system clock unless paused in tests
+fn retry_clock() -> Instant {
+    time::Instant::now().into_std()
+}
+
+// Trades are only told apart by what the feed sends
+fn same_trade(a: &Trade, b: &Trade) -> bool {
+    a.dt == b.dt && a.price == b.price && a.size == b.size
+}
+
+// Midpoint of a venue book, if it has both sides
+fn mid(book: &VenueBook) -> Option<Decimal> {
+    let bid = book.bids.keys().next_back()?;
+    let ask = book.asks.keys().next()?;
+    Some((bid + ask) / Decimal::TWO)
+}
+
+// Every price on each side of a book
+fn prices(book: &VenueBook) -> (Vec<Decimal>, Vec<Decimal>) {
+    (
+        book.bids.keys().copied().collect(),
+        book.asks.keys().copied().collect(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        feed::{Request, ScriptedFeed},
+        tape::Side,
+    };
+    use chrono::TimeZone;
+    use rust_decimal_macros::dec;
+
+    const CONFIG: &str = r#"
+        default_asset = "SOL"
+        assets = [
+            { name = "SOL", size_dp = 3, venues = ["kraken", "coinbase"] },
+            { name = "ETH", size_dp = 8, venues = ["kraken"] },
+        ]
+    "#;
+
+    fn engine() -> (Engine, ScriptedFeed) {
+        let config = Config::parse(CONFIG).unwrap();
+        let feed = ScriptedFeed::default();
+        (Engine::new(&config, Box::new(feed.clone())), feed)
+    }
+
+    fn channel(engine: &Engine, exchange: Exchange, channel: ChannelType) -> Channel {
+        Channel {
+            exchange,
+            channel,
+            market: engine.tickers["SOL"][&exchange].clone(),
+        }
+    }
+
+    fn book(bids: &[(Decimal, Decimal)], asks: &[(Decimal, Decimal)]) -> VenueBook {
+        VenueBook {
+            bids: bids.iter().cloned().collect(),
+            asks: asks.iter().cloned().collect(),
+        }
+    }
+
+    fn trade(exchange: Exchange, price: &str, secs: i64) -> Trade {
+        Trade {
+            size: "1".to_string(),
+            price: price.to_string(),
+            dt: Utc.timestamp_opt(1_700_000_000 + secs, 0).unwrap(),
+            exchange,
+        }
+    }
+
+    // Apply every completed subscribe request and every message the feed has ready
+    async fn deliver(engine: &mut Engine) {
+        while let Ok(Some((channel, result))) =
+            time::timeout(Duration::ZERO, engine.in_flight.next()).await
+        {
+            engine.subscribe_result(&channel, result);
+        }
+        while let Ok(Some(msg)) = time::timeout(Duration::ZERO, engine.source.recv()).await {
+            engine.response_handler(msg);
+        }
+    }
+
+    #[tokio::test]
+    pub async fn queue_subs() {
+        let (mut engine, feed) = engine();
+        engine.queue_subs().await;
+        // Only the selected asset's channels are queued, once
+        let channels = engine.get_channels("SOL");
+        assert_eq!(channels.len(), 4);
+        for c in channels.iter() {
+            assert_eq!(engine.subscriptions.state(c), Some(SubState::Queued));
+        }
+        engine.queue_subs().await;
+        assert_eq!(engine.subscriptions.iter().count(), 4);
+
+        let failing = channel(&engine, Exchange::Gdax, ChannelType::Book);
+        feed.fail(&failing, "rejected");
+        engine.subscribe_channels();
+        let requests = feed.take_requests();
+        assert_eq!(requests.len(), 4);
+        for c in channels.iter() {
+            assert!(requests.contains(&Request::Subscribe(c.clone())));
+        }
+        deliver(&mut engine).await;
+        for c in channels.iter() {
+            let expected = if *c == failing {
+                SubState::Failed
+            } else {
+                SubState::Subscribed
+            };
+            assert_eq!(engine.subscriptions.state(c), Some(expected));
+        }
+        let (_, entry) = engine
+            .subscriptions
+            .iter()
+            .find(|(c, _)| **c == failing)
+            .unwrap();
+        assert_eq!(entry.error.as_deref(), Some("rejected"));
+    }
+
+    // Retries 50ms after the first failure, then 100ms, giving up after three attempts
+    fn retrying_engine() -> (Engine, ScriptedFeed) {
+        let config = Config::parse(&format!(
+            "{}\n[subscriptions]\nretry_base_ms = 50\nretry_max_ms = 200\nmax_attempts = 3",
+            CONFIG
+        ))
+        .unwrap();
+        let feed = ScriptedFeed::default();
+        (Engine::new(&config, Box::new(feed.clone())), feed)
+    }
+
+    fn entry(engine: &Engine, channel: &Channel) -> SubEntry {
+        engine
+            .subscriptions
+            .iter()
+            .find(|(c, _)| *c == channel)
+            .map(|(_, e)| e.clone())
+            .unwrap()
+    }
+
+    // Request every due channel and apply the outcomes
+    async fn subscribe_round(engine: &mut Engine, feed: &ScriptedFeed) -> Vec<Request> {
+        engine.subscribe_channels();
+        deliver(engine).await;
+        feed.take_requests()
+    }
+
+    #[tokio::test(start_paused = true)]
+    pub async fn retries_until_subscribed() {
+        let (mut engine, feed) = retrying_engine();
+        let failing = channel(&engine, Exchange::Kraken, ChannelType::Book);
+        feed.fail(&failing, "connect failed");
+        engine.queue_subs().await;
+        assert_eq!(subscribe_round(&mut engine, &feed).await.len(), 4);
+        assert_eq!(entry(&engine, &failing).attempts, 1);
+        // Nothing is requested again before the backoff
+        assert!(subscribe_round(&mut engine, &feed).await.is_empty());
+
+        time::advance(Duration::from_millis(60)).await;
+        let before = retry_clock();
+        let requests = subscribe_round(&mut engine, &feed).await;
+        assert_eq!(requests, vec![Request::Subscribe(failing.clone())]);
+        // The second failure waits twice as long
+        let failed = entry(&engine, &failing);
+        assert_eq!(failed.state, SubState::Failed);
+        assert_eq!(failed.attempts, 2);
+        assert!(failed.retry_at.unwrap() >= before + Duration::from_millis(100));
+
+        feed.recover(&failing);
+        time::advance(Duration::from_millis(110)).await;
+        assert_eq!(subscribe_round(&mut engine, &feed).await.len(), 1);
+        assert_eq!(
+            engine.subscriptions.state(&failing),
+            Some(SubState::Subscribed)
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    pub async fn gives_up_after_max_attempts() {
+        let (mut engine, feed) = retrying_engine();
+        let failing = channel(&engine, Exchange::Kraken, ChannelType::Book);
+        feed.fail(&failing, "connect failed");
+        engine.queue_subs().await;
+        subscribe_round(&mut engine, &feed).await;
+        let mut attempts = 1;
+        while let Some(retry_at) = entry(&engine, &failing).retry_at {
+            time::advance(retry_at.saturating_duration_since(retry_clock())).await;
+            let requests = subscribe_round(&mut engine, &feed).await;
+            assert_eq!(requests, vec![Request::Subscribe(failing.clone())]);
+            attempts += 1;
+        }
+        assert_eq!(attempts, 3);
+        let failed = entry(&engine, &failing);
+        assert_eq!(failed.attempts, 3);
+        assert_eq!(failed.error.as_deref(), Some("connect failed"));
+        time::advance(Duration::from_millis(250)).await;
+        assert!(subscribe_round(&mut engine, &feed).await.is_empty());
+
+        // Forcing a resubscribe starts over
+        feed.recover(&failing);
+        engine.resubscribe();
+        assert_eq!(entry(&engine, &failing).attempts, 0);
+        assert_eq!(subscribe_round(&mut engine, &feed).await.len(), 1);
+        assert_eq!(
+            engine.subscriptions.state(&failing),
+            Some(SubState::Subscribed)
+        );
+    }
+
+    #[tokio::test]
+    pub async fn shared_markets() {
+        let (mut engine, feed) = engine();
+        engine.sub_config.keep_recent = 0;
+        engine.add_asset(&AssetConfig::parse_spec("SOL/USD 3 kraken").unwrap());
+        let kraken = channel(&engine, Exchange::Kraken, ChannelType::Book);
+        let coinbase = channel(&engine, Exchange::Gdax, ChannelType::Book);
+        assert!(engine.get_channels("SOL/USD").contains(&kraken));
+        engine.queue_subs().await;
+        engine.subscribe_channels();
+        deliver(&mut engine).await;
+        feed.take_requests();
+
+        // Leaving SOL for SOL/USD keeps the Kraken market both are built on
+        engine
+            .view("SOL/USD".to_string(), Some(AggExchange::Aggregate))
+            .await;
+        engine.expire_subs().await;
+        let unsubscribed: Vec<Channel> = feed
+            .take_requests()
+            .into_iter()
+            .filter_map(|r| match r {
+                Request::Unsubscribe(c) => Some(c),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(unsubscribed.len(), 2);
+        assert!(unsubscribed.iter().all(|c| c.exchange == Exchange::Gdax));
+        assert_eq!(
+            engine.subscriptions.state(&kraken),
+            Some(SubState::Subscribed)
+        );
+        assert_eq!(
+            engine.subscriptions.state(&coinbase),
+            Some(SubState::Unsubscribing)
+        );
+
+        // As does closing SOL
+        engine.remove_asset("SOL").await;
+        assert!(!feed
+            .take_requests()
+            .contains(&Request::Unsubscribe(kraken.clone())));
+        assert_eq!(
+            engine.subscriptions.state(&kraken),
+            Some(SubState::Subscribed)
+        );
+    }
+
+    #[tokio::test]
+    pub async fn requeued_before_unsubscribed() {
+        let (mut engine, feed) = engine();
+        let kraken = channel(&engine, Exchange::Kraken, ChannelType::Book);
+        engine.queue_subs().await;
+        engine.subscribe_channels();
+        deliver(&mut engine).await;
+        feed.push(
+            kraken.clone(),
+            Response::Book(book(&[(dec!(100), dec!(1))], &[(dec!(101), dec!(1))])),
+        );
+        deliver(&mut engine).await;
+        assert!(engine.books.contains_key(&kraken));
+
+        // The asset is left and opened again before the exchange acknowledges leaving it
+        engine.unsubscribe_asset("SOL").await;
+        assert_eq!(
+            engine.subscriptions.state(&kraken),
+            Some(SubState::Unsubscribing)
+        );
+        engine.queue_subs().await;
+        deliver(&mut engine).await;
+        assert_eq!(engine.subscriptions.state(&kraken), Some(SubState::Queued));
+        assert!(engine.books.contains_key(&kraken));
+        assert_eq!(engine.agg_books["SOL"].bids.len(), 1);
+
+        engine.subscribe_channels();
+        deliver(&mut engine).await;
+        assert_eq!(
+            engine.subscriptions.state(&kraken),
+            Some(SubState::Subscribed)
+        );
+    }
+
+    #[tokio::test]
+    pub async fn per_thousand_markets() {
+        let config = Config::parse(
+            r#"
+            assets = [{ name = "PEPE", size_dp = 0, venues = ["kraken", "hyperliquid"] }]
+        "#,
+        )
+        .unwrap();
+        let feed = ScriptedFeed::default();
+        let mut engine = Engine::new(&config, Box::new(feed.clone()));
+        let venue = |exchange, channel| Channel {
+            exchange,
+            channel,
+            market: engine.tickers["PEPE"][&exchange].clone(),
+        };
+        let kraken = venue(Exchange::Kraken, ChannelType::Book);
+        let hyperliquid = venue(Exchange::Hyperliquid, ChannelType::Book);
+        let hyperliquid_tape = venue(Exchange::Hyperliquid, ChannelType::Tape);
+        assert_eq!(hyperliquid.market, "kPEPE");
+        feed.push(
+            kraken,
+            Response::Book(book(&[(dec!(0.00001), dec!(1000000))], &[])),
+        );
+        // 2,000 kPEPE at 0.01 is 2,000,000 PEPE at 0.00001
+        feed.push(
+            hyperliquid,
+            Response::Book(book(&[(dec!(0.01), dec!(2000))], &[])),
+        );
+        let mut trade = trade(Exchange::Hyperliquid, "0.011", 0);
+        trade.size = "5".to_string();
+        feed.push(hyperliquid_tape, Response::Tape(VecDeque::from([trade])));
+        deliver(&mut engine).await;
+        engine.update_state();
+        assert_eq!(engine.book.bids.len(), 1);
+        assert_eq!(engine.book.bids[&dec!(0.00001)].size, dec!(3000000));
+        assert_eq!(engine.trades[0].price, dec!(0.000011));
+        assert_eq!(engine.trades[0].size, dec!(5000));
+    }
+
+    #[tokio::test]
+    pub async fn stalled_subscribe() {
+        let config = Config::parse(&format!(
+            "{}\n[subscriptions]\nrequest_timeout_ms = 50\nmax_parallel = 3",
+            CONFIG
+        ))
+        .unwrap();
+        let feed = ScriptedFeed::default();
+        let mut engine = Engine::new(&config, Box::new(feed.clone()));
+        let stalled = channel(&engine, Exchange::Kraken, ChannelType::Book);
+        feed.stall(&stalled);
+        engine.queue_subs().await;
+
+        // Requests are sent without waiting on the connect that hangs, up to the parallelism
+        engine.subscribe_channels();
+        assert_eq!(feed.take_requests().len(), 3);
+        assert!(!engine.has_due_subs());
+        deliver(&mut engine).await;
+        // Answered requests make room for the rest of the queue
+        assert!(engine.has_due_subs());
+        engine.subscribe_channels();
+        assert_eq!(feed.take_requests().len(), 1);
+        deliver(&mut engine).await;
+        assert_eq!(
+            engine.subscriptions.state(&stalled),
+            Some(SubState::Requesting)
+        );
+        let subscribed = engine
+            .subscriptions
+            .iter()
+            .filter(|(_, e)| e.state == SubState::Subscribed)
+            .count();
+        assert_eq!(subscribed, 3);
+
+        // The hung request fails once it times out
+        let (channel, result) = engine.in_flight.next().await.unwrap();
+        engine.subscribe_result(&channel, result);
+        assert_eq!(channel, stalled);
+        assert_eq!(engine.subscriptions.state(&stalled), Some(SubState::Failed));
+        assert!(engine.in_flight.is_empty());
+    }
+
+    #[tokio::test]
+    pub async fn poll_data() {
+        let (mut engine, feed) = engine();
+        let kraken = channel(&engine, Exchange::Kraken, ChannelType::Book);
+        let kraken_tape = channel(&engine, Exchange::Kraken, ChannelType::Tape);
+        feed.set_book(
+            &kraken,
+            book(&[(dec!(100), dec!(1))], &[(dec!(101), dec!(1))]),
+        );
+        feed.set_tape(
+            &kraken_tape,
+            VecDeque::from([trade(Exchange::Kraken, "100.5", 0)]),
+        );
+
+        // Nothing has arrived yet so every channel of the view is polled
+        engine.poll_data().await;
+        let requests = feed.take_requests();
+        assert_eq!(requests.len(), 4);
+        assert!(requests.contains(&Request::Book(kraken.clone())));
+        deliver(&mut engine).await;
+        assert!(engine.books.contains_key(&kraken));
+        assert_eq!(engine.tapes[&kraken_tape].len(), 1);
+
+        // Nothing is polled again within the interval
+        engine.poll_data().await;
+        assert!(feed.take_requests().is_empty());
+        let ago = |d: Duration| Instant::now() - d;
+        let polled_ago = |engine: &mut Engine, intervals: u32| {
+            let at = ago(engine.poll_interval * intervals);
+            for polled in engine.last_poll.values_mut() {
+                *polled = at;
+            }
+        };
+        polled_ago(&mut engine, 1);
+        engine.poll_data().await;
+        assert_eq!(feed.take_requests().len(), 4);
+
+        // Kraken has not changed for a while so it is polled less often than Coinbase, which has
+        // sent nothing yet
+        engine
+            .last_update
+            .insert(kraken.clone(), ago(Duration::from_secs(10)));
+        engine
+            .last_update
+            .insert(kraken_tape.clone(), ago(Duration::from_secs(10)));
+        polled_ago(&mut engine, 1);
+        engine.poll_data().await;
+        let requests = feed.take_requests();
+        assert_eq!(requests.len(), 2);
+        assert!(requests.iter().all(|r| match r {
+            Request::Tape(c) | Request::Book(c) => c.exchange == Exchange::Gdax,
+            _ => false,
+        }));
+        polled_ago(&mut engine, MAX_POLL_BACKOFF);
+        engine.poll_data().await;
+        assert_eq!(feed.take_requests().len(), 4);
+
+        engine.fixed_poll = true;
+        engine.poll_data().await;
+        assert_eq!(feed.take_requests().len(), 4);
+
+        // A single venue view only polls that venue
+        engine
+            .view(
+                "SOL".to_string(),
+                Some(AggExchange::Exchange(Exchange::Gdax)),
+            )
+            .await;
+        engine.poll_data().await;
+        let requests = feed.take_requests();
+        assert_eq!(
+            requests,
+            vec![
+                Request::Tape(channel(&engine, Exchange::Gdax, ChannelType::Tape)),
+                Request::Book(channel(&engine, Exchange::Gdax, ChannelType::Book)),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    pub async fn tape_dedupe() {
+        let (mut engine, _) = engine();
+        let tape = channel(&engine, Exchange::Kraken, ChannelType::Tape);
+        let at = |price: &str, secs| trade(Exchange::Kraken, price, secs);
+        let mut received = VecDeque::from([at("100", 0), at("101", 1)]);
+        engine.handle_tape(tape.clone(), received.clone(), Utc::now());
+        assert_eq!(engine.tapes[&tape].len(), 2);
+
+        // A fill at the newest timestamp seen arrives in a later poll, then a repeat of it
+        received.push_back(at("102", 1));
+        engine.handle_tape(tape.clone(), received.clone(), Utc::now());
+        received.push_back(at("102", 1));
+        engine.handle_tape(tape.clone(), received.clone(), Utc::now());
+        // Nothing new
+        engine.handle_tape(tape.clone(), received.clone(), Utc::now());
+        let prices: Vec<Decimal> = engine.tapes[&tape].iter().map(|t| t.price).collect();
+        assert_eq!(prices, vec![dec!(100), dec!(101), dec!(102), dec!(102)]);
+
+        // Older trades rolling off the venue's tape are not received again
+        received.pop_front();
+        received.push_back(at("103", 2));
+        engine.handle_tape(tape.clone(), received, Utc::now());
+        assert_eq!(engine.tapes[&tape].len(), 5);
+        assert_eq!(engine.dropped_trades, 0);
+    }
+
+    #[tokio::test]
+    pub async fn side_at_trade_time() {
+        let (mut engine, _) = engine();
+        let tape = channel(&engine, Exchange::Kraken, ChannelType::Tape);
+        let book_channel = channel(&engine, Exchange::Kraken, ChannelType::Book);
+        let at = |secs: i64| Utc.timestamp_opt(1_700_000_000 + secs, 0).unwrap();
+        let quote = |bid, ask| book(&[(bid, dec!(1))], &[(ask, dec!(1))]);
+        engine.handle_book(book_channel.clone(), quote(dec!(99), dec!(101)), at(0));
+        // The book moves up before a poll returns a trade made when the mid was 100
+        engine.handle_book(book_channel.clone(), quote(dec!(102), dec!(104)), at(10));
+        let trades = VecDeque::from([
+            trade(Exchange::Kraken, "100", -5),
+            trade(Exchange::Kraken, "100.5", 5),
+            trade(Exchange::Kraken, "102", 12),
+        ]);
+        engine.handle_tape(tape.clone(), trades, at(12));
+        let sides: Vec<Option<Side>> = engine.tapes[&tape].iter().map(|t| t.side).collect();
+        // Before either book only the tick rule applies, which needs a previous trade
+        assert_eq!(sides, vec![None, Some(Side::Buy), Some(Side::Sell)]);
+    }
+
+    #[tokio::test]
+    pub async fn update_state() {
+        let (mut engine, feed) = engine();
+        let kraken = |c| channel(&engine, Exchange::Kraken, c);
+        let coinbase = |c| channel(&engine, Exchange::Gdax, c);
+        feed.push(
+            kraken(ChannelType::Book),
+            Response::Book(book(&[(dec!(100), dec!(1))], &[(dec!(101), dec!(2))])),
+        );
+        feed.push(
+            coinbase(ChannelType::Book),
+            Response::Book(book(
+                &[(dec!(100), dec!(3)), (dec!(99), dec!(1))],
+                &[(dec!(102), dec!(1))],
+            )),
+        );
+        feed.push(
+            kraken(ChannelType::Tape),
+            Response::Tape(VecDeque::from([trade(Exchange::Kraken, "100.5", 0)])),
+        );
+        feed.push(
+            coinbase(ChannelType::Tape),
+            Response::Tape(VecDeque::from([trade(Exchange::Gdax, "101", 1)])),
+        );
+        deliver(&mut engine).await;
+
+        // The aggregate merges both venues, newest trade first
+        engine.update_state();
+        let level = &engine.book.bids[&dec!(100)];
+        assert_eq!(level.size, dec!(4));
+        // Venues are listed in config order
+        assert_eq!(
+            level.venues,
+            vec![(Exchange::Kraken, dec!(1)), (Exchange::Gdax, dec!(3))]
+        );
+        assert_eq!(engine.book.bids.len(), 2);
+        assert_eq!(engine.book.asks.len(), 2);
+        let prices: Vec<Decimal> = engine.trades.iter().map(|t| t.price).collect();
+        assert_eq!(prices, vec![dec!(101), dec!(100.5)]);
+
+        engine
+            .view(
+                "SOL".to_string(),
+                Some(AggExchange::Exchange(Exchange::Kraken)),
+            )
+            .await;
+        engine.update_state();
+        assert_eq!(engine.book.bids[&dec!(100)].size, dec!(1));
+        assert_eq!(engine.book.asks.len(), 1);
+        assert_eq!(engine.trades.len(), 1);
+        assert_eq!(engine.trades[0].exchange, Exchange::Kraken);
+
+        engine.view("SOL".to_string(), None).await;
+        engine.update_state();
+        assert!(engine.book.bids.is_empty());
+        assert!(engine.trades.is_empty());
+    }
+}
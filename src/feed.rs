@@ -0,0 +1,327 @@
+#[cfg(test)]
+use std::{
+    collections::HashMap,
+    sync::{Mutex, MutexGuard},
+};
+use std::{collections::VecDeque, sync::Arc, time::Instant};
+
+use agg_ws::{
+    client::{AsyncClient, Channel, ClientError, ClientResp, ClientRespMsg},
+    trades::Trade,
+};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use futures::future::{BoxFuture, FutureExt};
+use rust_decimal::Decimal;
+use tokio::sync::mpsc;
+
+use crate::{
+    book::Levels,
+    config::AssetConfig,
+    replay::{ReplayControl, ReplayStatus, Seek},
+};
+
+/// A venue's full book as last sent.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct VenueBook {
+    pub bids: Levels,
+    pub asks: Levels,
+}
+
+impl VenueBook {
+    /// The book in single units of the base asset, for a market quoting `units` at a time.
+    pub fn per_unit(self, units: Decimal) -> VenueBook {
+        if units == Decimal::ONE {
+            return self;
+        }
+        let convert = |levels: Levels| -> Levels {
+            levels
+                .into_iter()
+                .map(|(price, size)| (price / units, size * units))
+                .collect()
+        };
+        VenueBook {
+            bids: convert(self.bids),
+            asks: convert(self.asks),
+        }
+    }
+}
+
+/// A response from the market data source and when it was received.
+#[derive(Debug)]
+pub struct Message {
+    pub channel: Channel,
+    pub resp: Response,
+    pub received_at: DateTime<Utc>,
+}
+
+#[derive(Debug)]
+pub enum Response {
+    Subscribed,
+    Unsubscribed,
+    Tape(VecDeque<Trade>),
+    Book(VenueBook),
+    /// Last raw message, only logged
+    Last,
+}
+
+impl From<ClientRespMsg> for Message {
+    fn from(msg: ClientRespMsg) -> Self {
+        let resp = match msg.resp {
+            ClientResp::Subscribed => Response::Subscribed,
+            ClientResp::Unsubscribed => Response::Unsubscribed,
+            ClientResp::Tape(tape) => Response::Tape(tape),
+            ClientResp::Book(book) => Response::Book(VenueBook {
+                bids: book.bids,
+                asks: book.asks,
+            }),
+            ClientResp::Last(_) => Response::Last,
+        };
+        Message {
+            channel: msg.channel,
+            resp,
+            received_at: Utc::now(),
+        }
+    }
+}
+
+/// A source of market data: the exchanges through the websocket client, a recording or a script.
+/// Responses to requests arrive through `recv` rather than as return values.
+#[async_trait]
+pub trait MarketData: Send + Sync {
+    /// Request a subscription. The request does not borrow the source, so a slow connect can be
+    /// awaited alongside `recv` and other requests.
+    fn subscribe(&self, channel: Channel) -> BoxFuture<'static, Result<(), String>>;
+
+    async fn unsubscribe(&self, channel: Channel) -> Result<(), String>;
+
+    /// Ask for the channel's current tape.
+    async fn get_tape(&self, channel: Channel) -> Result<(), String>;
+
+    /// Ask for the channel's current book.
+    async fn get_book(&self, channel: Channel) -> Result<(), String>;
+
+    /// The next response, None if the source sent an error.
+    async fn recv(&mut self) -> Option<Message>;
+
+    /// Current time on the data's clock, which runs at the playback speed when replaying.
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    /// Playback position, only for recordings.
+    fn replay_status(&self) -> Option<ReplayStatus> {
+        None
+    }
+
+    /// Apply a playback control, returning any messages to apply at once.
+    fn replay_control(&mut self, _control: ReplayControl) -> Option<Seek> {
+        None
+    }
+
+    /// An asset was opened, for sources that generate its data.
+    fn add_asset(&mut self, _asset: &AssetConfig) {}
+}
+
+/// The exchanges through the websocket client. The client's receiver is taken out of it so that
+/// responses can be received while requests are in flight.
+pub struct Exchanges {
+    client: Arc<AsyncClient>,
+    receiver: mpsc::Receiver<Result<ClientRespMsg, ClientError>>,
+}
+
+impl Exchanges {
+    pub fn new() -> Exchanges {
+        let mut client = AsyncClient::new();
+        let (_, closed) = mpsc::channel(1);
+        let receiver = std::mem::replace(&mut client.receiver, closed);
+        Exchanges {
+            client: Arc::new(client),
+            receiver,
+        }
+    }
+}
+
+#[async_trait]
+impl MarketData for Exchanges {
+    fn subscribe(&self, channel: Channel) -> BoxFuture<'static, Result<(), String>> {
+        let client = self.client.clone();
+        async move {
+            let resp = client
+                .start_and_subscribe(channel)
+                .await
+                .map_err(|e| format!("{:?}", e))?;
+            tracing::info!("Sub req resp: {:?}", resp);
+            Ok(())
+        }
+        .boxed()
+    }
+
+    async fn unsubscribe(&self, channel: Channel) -> Result<(), String> {
+        let resp = self
+            .client
+            .unsubscribe(channel)
+            .await
+            .map_err(|e| format!("{:?}", e))?;
+        tracing::info!("Unsub req resp: {:?}", resp);
+        Ok(())
+    }
+
+    async fn get_tape(&self, channel: Channel) -> Result<(), String> {
+        self.client
+            .get_tape(channel)
+            .await
+            .map(|_| ())
+            .map_err(|e| format!("{:?}", e))
+    }
+
+    async fn get_book(&self, channel: Channel) -> Result<(), String> {
+        self.client
+            .get_book(channel)
+            .await
+            .map(|_| ())
+            .map_err(|e| format!("{:?}", e))
+    }
+
+    async fn recv(&mut self) -> Option<Message> {
+        match self.receiver.recv().await? {
+            Ok(msg) => Some(msg.into()),
+            Err(e) => {
+                tracing::error!("Client error: {:?}", e);
+                None
+            }
+        }
+    }
+}
+
+/// A request made to a `ScriptedFeed`.
+#[cfg(test)]
+#[derive(Debug, Clone, PartialEq)]
+pub enum Request {
+    Subscribe(Channel),
+    Unsubscribe(Channel),
+    Tape(Channel),
+    Book(Channel),
+}
+
+/// Market data from a script, for tests. Every request is logged, subscriptions are acknowledged
+/// unless set to fail and tape and book requests are answered with whatever was set for the
+/// channel. Clones share the script so a test can keep one after handing another to the engine.
+#[cfg(test)]
+#[derive(Clone, Default)]
+pub struct ScriptedFeed {
+    script: Arc<Mutex<Script>>,
+}
+
+#[cfg(test)]
+#[derive(Default)]
+struct Script {
+    requests: Vec<Request>,
+    pending: VecDeque<Message>,
+    tapes: HashMap<Channel, VecDeque<Trade>>,
+    books: HashMap<Channel, VenueBook>,
+    failing: HashMap<Channel, String>,
+    /// Channels whose subscribe requests never complete
+    stalled: Vec<Channel>,
+}
+
+#[cfg(test)]
+impl ScriptedFeed {
+    fn script(&self) -> MutexGuard<'_, Script> {
+        self.script.lock().unwrap()
+    }
+
+    /// Queue a message as if pushed by the exchange.
+    pub fn push(&self, channel: Channel, resp: Response) {
+        self.script().pending.push_back(Message {
+            channel,
+            resp,
+            received_at: Utc::now(),
+        });
+    }
+
+    pub fn set_tape(&self, channel: &Channel, tape: VecDeque<Trade>) {
+        self.script().tapes.insert(channel.clone(), tape);
+    }
+
+    pub fn set_book(&self, channel: &Channel, book: VenueBook) {
+        self.script().books.insert(channel.clone(), book);
+    }
+
+    /// Reject subscribe requests for the channel with `error`.
+    pub fn fail(&self, channel: &Channel, error: &str) {
+        self.script()
+            .failing
+            .insert(channel.clone(), error.to_string());
+    }
+
+    /// Acknowledge subscribe requests for the channel again.
+    pub fn recover(&self, channel: &Channel) {
+        self.script().failing.remove(channel);
+    }
+
+    /// Leave subscribe requests for the channel unanswered, like a connect that hangs.
+    pub fn stall(&self, channel: &Channel) {
+        self.script().stalled.push(channel.clone());
+    }
+
+    /// Requests made since the last call.
+    pub fn take_requests(&self) -> Vec<Request> {
+        std::mem::take(&mut self.script().requests)
+    }
+}
+
+#[cfg(test)]
+#[async_trait]
+impl MarketData for ScriptedFeed {
+    fn subscribe(&self, channel: Channel) -> BoxFuture<'static, Result<(), String>> {
+        let mut script = self.script();
+        script.requests.push(Request::Subscribe(channel.clone()));
+        if script.stalled.contains(&channel) {
+            return futures::future::pending().boxed();
+        }
+        if let Some(error) = script.failing.get(&channel) {
+            return futures::future::ready(Err(error.clone())).boxed();
+        }
+        drop(script);
+        self.push(channel, Response::Subscribed);
+        futures::future::ready(Ok(())).boxed()
+    }
+
+    async fn unsubscribe(&self, channel: Channel) -> Result<(), String> {
+        self.script()
+            .requests
+            .push(Request::Unsubscribe(channel.clone()));
+        self.push(channel, Response::Unsubscribed);
+        Ok(())
+    }
+
+    async fn get_tape(&self, channel: Channel) -> Result<(), String> {
+        let mut script = self.script();
+        script.requests.push(Request::Tape(channel.clone()));
+        if let Some(tape) = script.tapes.get(&channel).cloned() {
+            drop(script);
+            self.push(channel, Response::Tape(tape));
+        }
+        Ok(())
+    }
+
+    async fn get_book(&self, channel: Channel) -> Result<(), String> {
+        let mut script = self.script();
+        script.requests.push(Request::Book(channel.clone()));
+        if let Some(book) = script.books.get(&channel).cloned() {
+            drop(script);
+            self.push(channel, Response::Book(book));
+        }
+        Ok(())
+    }
+
+    /// The next queued message, waiting forever once the script has run out.
+    async fn recv(&mut self) -> Option<Message> {
+        let msg = self.script().pending.pop_front();
+        match msg {
+            Some(msg) => Some(msg),
+            None => std::future::pending().await,
+        }
+    }
+}
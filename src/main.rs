@@ -1,123 +1,194 @@
 mod app;
+mod arb;
+mod bbo;
+mod book;
+mod cli;
+mod config;
+mod demo;
+mod engine;
+mod feed;
+mod record;
+mod replay;
+mod subscriptions;
+mod sweep;
+mod symbols;
+mod tape;
 mod ui;
 
-use std::{collections::BTreeMap, error::Error, fs::File, io, panic, sync::Arc};
+use std::{error::Error, fs::File, io, panic, sync::Arc};
 
-use agg_ws::client::Exchange;
 use app::App;
+pub use book::{AggBook, AggExchange, Level};
+use clap::Parser;
+use cli::{Cli, Mode};
+use config::Config;
 use crossterm::{
     event::{Event, EventStream, KeyEventKind},
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
 };
+use demo::Demo;
+use engine::{Engine, Snapshot};
+use feed::{Exchanges, MarketData};
 use futures::StreamExt;
 use ratatui::{
     backend::{Backend, CrosstermBackend},
     Terminal,
 };
-use rust_decimal::Decimal;
-use tokio::{time, time::Duration};
+use record::Recorder;
+use replay::Replay;
+use tokio::{
+    sync::{mpsc, watch},
+    task::JoinHandle,
+    time::{self, Duration, MissedTickBehavior},
+};
 use ui::ui;
 
 type Result<T> = std::result::Result<T, Box<dyn Error>>;
 
-#[derive(Debug, Clone)]
-pub enum AggExchange {
-    Aggregate,
-    Exchange(Exchange),
-}
-
-impl AggExchange {
-    pub fn as_display(&self) -> &'static str {
-        match self {
-            Self::Aggregate => "Aggregate",
-            Self::Exchange(Exchange::Gdax) => "Coinbase",
-            Self::Exchange(Exchange::Kraken) => "Kraken",
-            Self::Exchange(Exchange::Hyperliquid) => "Hyperliquid",
-        }
-    }
-}
-
-#[derive(Debug, Clone)]
-pub struct AggBook {
-    pub bids: BTreeMap<Decimal, Level>,
-    pub asks: BTreeMap<Decimal, Level>,
-}
-
-impl AggBook {
-    pub fn new() -> Self {
-        AggBook {
-            bids: BTreeMap::new(),
-            asks: BTreeMap::new(),
-        }
-    }
-}
-
-impl Default for AggBook {
-    fn default() -> Self {
-        Self::new()
-    }
-}
-
-#[derive(Debug, Clone)]
-pub struct Level {
-    pub size: Decimal,
-    pub exchange: AggExchange,
-}
-
 #[tokio::main]
 async fn main() -> Result<()> {
+    let cli = Cli::parse();
     better_panic::install();
-    setup_panic_hook();
-    // tracing_subscriber::fmt::init();
 
     // A layer that logs events to a file.
-    let file = File::create("debug.log");
+    let file = File::create(&cli.log_file);
     let file = match file {
         Ok(file) => file,
         Err(error) => panic!("Error: {:?}", error),
     };
-    let log = tracing_subscriber::fmt().with_writer(Arc::new(file));
+    let log = tracing_subscriber::fmt()
+        .with_max_level(cli.log_level)
+        .with_writer(Arc::new(file));
     log.init();
 
-    let mut terminal = init_terminal()?;
-    let mut app = App::new()?;
+    // Load and validate the config before taking over the terminal so errors are readable
+    let mut config = Config::load(cli.config.as_deref())?;
+    let mode = cli.mode();
+    // Replays show the assets that were recorded, whatever is configured now
+    let source: Box<dyn MarketData> = match &mode {
+        Mode::Replay { files } => {
+            let (header, replay) = Replay::load(files)?;
+            config.replay_assets(&header.assets, &header.venues)?;
+            Box::new(replay)
+        }
+        Mode::Tui | Mode::Headless if cli.demo => Box::new(Demo::new(&config.demo)),
+        Mode::Tui | Mode::Headless => Box::new(Exchanges::new()),
+    };
+    let replaying = matches!(mode, Mode::Replay { .. });
+    if let Some(asset) = cli.asset.as_deref() {
+        config.select_asset(asset)?;
+    }
+    if !cli.exchanges.is_empty() {
+        config.restrict_venues(&cli.exchanges)?;
+    }
+    let interval = Duration::from_millis(cli.interval);
+    let frame = Duration::from_secs(1) / cli.fps;
+
+    let mut engine = Engine::new(&config, source);
+    engine.fixed_poll = cli.fixed_poll;
+    engine.poll_interval = interval;
+    if let Some(path) = config.arbitrage.log_file.as_deref().filter(|_| !replaying) {
+        engine.cross_log.open(path)?;
+    }
+    let recorder = if cli.record && !replaying {
+        Some(Recorder::new(
+            &config.record,
+            &engine.tickers,
+            &engine.venues,
+        )?)
+    } else {
+        None
+    };
+    engine.recorder = recorder.clone();
     // Add initial subs to queue
-    app.queue_subs().await;
-
-    run(&mut terminal, &mut app).await?;
-
-    reset_terminal()?;
+    engine.queue_subs().await;
+    let (commands, command_rx) = mpsc::unbounded_channel();
+    let (snapshot_tx, snapshots) = watch::channel(Arc::new(Snapshot::default()));
+    // Installed before the engine starts so that a panic in either task flushes the recording
+    setup_panic_hook(recorder.clone(), !matches!(mode, Mode::Headless));
+    let mut engine = tokio::spawn(engine.run(command_rx, snapshot_tx, interval, frame));
+
+    match mode {
+        Mode::Tui | Mode::Replay { .. } => {
+            let mut app = App::new(&config, commands)?;
+            let mut terminal = init_terminal()?;
+            let result = run(&mut terminal, &mut app, snapshots, frame).await;
+            reset_terminal()?;
+            // Dropping the app closes the command channel, which stops the engine
+            drop(app);
+            stop_engine(engine).await;
+            if let Some(recorder) = &recorder {
+                recorder.flush();
+            }
+            result?;
+        }
+        Mode::Headless => {
+            // The engine runs until the command sender is dropped
+            let stopped = tokio::select! {
+                _ = &mut engine => true,
+                _ = tokio::signal::ctrl_c() => false,
+            };
+            if !stopped {
+                drop(commands);
+                stop_engine(engine).await;
+            }
+            if let Some(recorder) = &recorder {
+                recorder.flush();
+            }
+        }
+    }
     Ok(())
 }
 
-async fn run<B: Backend>(terminal: &mut Terminal<B>, app: &mut App) -> Result<()> {
+/// Waits briefly for the engine to finish after its command channel closed, so it can log the
+/// crosses still open.
+async fn stop_engine(engine: JoinHandle<()>) {
+    if time::timeout(Duration::from_secs(1), engine).await.is_err() {
+        tracing::warn!("Engine did not stop in time");
+    }
+}
+
+/// Runs the UI event loop. Market data arrives as snapshots from the engine task and the screen
+/// is redrawn at a fixed frame rate whenever a new snapshot or a key press changed something.
+async fn run<B: Backend>(
+    terminal: &mut Terminal<B>,
+    app: &mut App,
+    mut snapshots: watch::Receiver<Arc<Snapshot>>,
+    frame: Duration,
+) -> Result<()> {
     let mut reader = EventStream::new();
-    let mut interval = time::interval(Duration::from_millis(350));
+    let mut frames = time::interval(frame);
+    frames.set_missed_tick_behavior(MissedTickBehavior::Skip);
+    let mut dirty = true;
 
     loop {
-        app.update_state();
-        terminal.draw(|f| ui(f, app))?;
-        // app.manage_state().await;
-
         tokio::select! {
-            _ = interval.tick() => app.manage_state().await,
+            // Input first so the screen never lags behind the keyboard
+            biased;
             maybe_event = reader.next() => {
                 match maybe_event {
                     Some(Ok(Event::Key(key_event))) => {
-                        if key_event.kind == KeyEventKind::Press && !app.handle_key_press(&key_event).await {
+                        if key_event.kind == KeyEventKind::Press && !app.handle_key_press(&key_event) {
                                 break
                         }
+                        dirty = true;
                     },
+                    Some(Ok(Event::Resize(_, _))) => dirty = true,
                     Some(Ok(_)) => {},
                     Some(Err(_)) => { break },
                     None => {},
                 }
             },
-            msg = app.client.receiver.recv() => {
-                if let Some(Ok(msg)) = msg {
-                    app.response_handler(msg);
-                }
-            }
+            changed = snapshots.changed() => match changed {
+                Ok(()) => dirty = true,
+                // The engine task has stopped
+                Err(_) => break,
+            },
+            _ = frames.tick(), if dirty => {
+                app.snapshot = snapshots.borrow_and_update().clone();
+                terminal.draw(|f| ui(f, app))?;
+                dirty = false;
+            },
         };
     }
     Ok(())
@@ -144,9 +215,15 @@ fn reset_terminal() -> Result<()> {
     Ok(())
 }
 
-fn setup_panic_hook() {
-    panic::set_hook(Box::new(|panic_info| {
-        reset_terminal().unwrap();
+/// Flushes the recording and, when drawing, restores the terminal before reporting a panic.
+fn setup_panic_hook(recorder: Option<Recorder>, terminal: bool) {
+    panic::set_hook(Box::new(move |panic_info| {
+        if let Some(recorder) = &recorder {
+            recorder.flush();
+        }
+        if terminal {
+            let _ = reset_terminal();
+        }
         better_panic::Settings::auto().create_panic_handler()(panic_info);
     }));
 }
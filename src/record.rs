@@ -0,0 +1,366 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs::{self, File, OpenOptions},
+    io::{self, BufWriter, Write},
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use agg_ws::client::{ChannelType, Exchange};
+use chrono::Utc;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::{exchange_name, RecordConfig},
+    feed::{Message, Response},
+};
+
+/// Version written in each file header, bumped when the record layout changes.
+pub const FORMAT_VERSION: u32 = 1;
+
+/// First line of every recording file: the assets open when the file was started and the market
+/// symbol of each of their venues.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Header {
+    pub version: u32,
+    /// Microseconds since the epoch
+    pub started: i64,
+    pub assets: BTreeMap<String, BTreeMap<String, String>>,
+    /// Venues of each asset in config order, absent from older recordings
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub venues: BTreeMap<String, Vec<String>>,
+}
+
+/// One client response and the local time it was received, one per line after the header.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Record {
+    /// Receive time in microseconds since the epoch
+    pub t: i64,
+    pub ex: String,
+    pub ch: String,
+    pub m: String,
+    #[serde(flatten)]
+    pub resp: RecordedResp,
+}
+
+/// Response variant and payload. Trades keep their prices and sizes as sent so records that fail
+/// validation are recorded too. `Last` responses are recorded without their payload.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "r", content = "d", rename_all = "lowercase")]
+pub enum RecordedResp {
+    Subscribed,
+    Unsubscribed,
+    Tape(Vec<RecordedTrade>),
+    Book {
+        bids: Vec<(Decimal, Decimal)>,
+        asks: Vec<(Decimal, Decimal)>,
+    },
+    Last,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecordedTrade {
+    pub p: String,
+    pub s: String,
+    /// Exchange timestamp in microseconds since the epoch
+    pub t: i64,
+}
+
+impl Record {
+    pub fn new(msg: &Message) -> Record {
+        let resp = match &msg.resp {
+            Response::Subscribed => RecordedResp::Subscribed,
+            Response::Unsubscribed => RecordedResp::Unsubscribed,
+            Response::Tape(tape) => RecordedResp::Tape(
+                tape.iter()
+                    .map(|t| RecordedTrade {
+                        p: t.price.clone(),
+                        s: t.size.clone(),
+                        t: t.dt.timestamp_micros(),
+                    })
+                    .collect(),
+            ),
+            Response::Book(book) => RecordedResp::Book {
+                bids: book.bids.iter().map(|(p, s)| (*p, *s)).collect(),
+                asks: book.asks.iter().map(|(p, s)| (*p, *s)).collect(),
+            },
+            Response::Last => RecordedResp::Last,
+        };
+        Record {
+            t: msg.received_at.timestamp_micros(),
+            ex: exchange_name(msg.channel.exchange).to_string(),
+            ch: match msg.channel.channel {
+                ChannelType::Tape => "tape",
+                ChannelType::Book => "book",
+            }
+            .to_string(),
+            m: msg.channel.market.clone(),
+            resp,
+        }
+    }
+}
+
+/// Appends every client response to files in the recordings directory, starting a new file
+/// once the current one reaches the configured size or age. Clones share the open file so one
+/// can be kept to flush on exit or from the panic hook.
+#[derive(Clone)]
+pub struct Recorder {
+    files: Arc<Mutex<RecordFiles>>,
+}
+
+struct RecordFiles {
+    dir: PathBuf,
+    max_bytes: u64,
+    max_age: Duration,
+    assets: BTreeMap<String, BTreeMap<String, String>>,
+    venues: BTreeMap<String, Vec<String>>,
+    file: Option<BufWriter<File>>,
+    written: u64,
+    opened: Instant,
+}
+
+impl Recorder {
+    /// Create the recordings directory and open the first file.
+    pub fn new(
+        config: &RecordConfig,
+        tickers: &HashMap<String, HashMap<Exchange, String>>,
+        venues: &HashMap<String, Vec<Exchange>>,
+    ) -> io::Result<Recorder> {
+        fs::create_dir_all(&config.dir)?;
+        let mut files = RecordFiles {
+            dir: config.dir.clone(),
+            max_bytes: config.max_file_mb * 1_000_000,
+            max_age: Duration::from_secs(config.max_file_secs),
+            assets: BTreeMap::new(),
+            venues: BTreeMap::new(),
+            file: None,
+            written: 0,
+            opened: Instant::now(),
+        };
+        files.set_assets(tickers, venues);
+        files.rotate()?;
+        Ok(Recorder {
+            files: Arc::new(Mutex::new(files)),
+        })
+    }
+
+    pub fn record(&self, msg: &Message) {
+        self.write(&Record::new(msg));
+    }
+
+    pub fn write(&self, record: &Record) {
+        let mut files = self.files.lock().unwrap_or_else(|e| e.into_inner());
+        if let Err(e) = files.write(record) {
+            tracing::error!("Failed to write recording: {:?}", e);
+        }
+    }
+
+    /// Update the assets as they are opened and closed, starting a new file so that its header
+    /// describes every asset recorded in it.
+    pub fn set_assets(
+        &self,
+        tickers: &HashMap<String, HashMap<Exchange, String>>,
+        venues: &HashMap<String, Vec<Exchange>>,
+    ) {
+        let mut files = self.files.lock().unwrap_or_else(|e| e.into_inner());
+        if files.set_assets(tickers, venues) {
+            if let Err(e) = files.rotate() {
+                tracing::error!("Failed to start recording file: {:?}", e);
+            }
+        }
+    }
+
+    /// Write out buffered records. Does not wait for the file, so it is safe to call from a panic
+    /// hook even if the panic happened mid-write.
+    pub fn flush(&self) {
+        if let Ok(mut files) = self.files.try_lock() {
+            if let Some(file) = files.file.as_mut() {
+                if let Err(e) = file.flush() {
+                    tracing::error!("Failed to flush recording: {:?}", e);
+                }
+            }
+        }
+    }
+}
+
+impl RecordFiles {
+    // Returns whether the assets changed
+    fn set_assets(
+        &mut self,
+        tickers: &HashMap<String, HashMap<Exchange, String>>,
+        venues: &HashMap<String, Vec<Exchange>>,
+    ) -> bool {
+        let assets: BTreeMap<String, BTreeMap<String, String>> = tickers
+            .iter()
+            .map(|(asset, markets)| {
+                let markets = markets
+                    .iter()
+                    .map(|(ex, m)| (exchange_name(*ex).to_string(), m.clone()))
+                    .collect();
+                (asset.clone(), markets)
+            })
+            .collect();
+        let venues: BTreeMap<String, Vec<String>> = venues
+            .iter()
+            .map(|(asset, venues)| {
+                let venues = venues.iter().map(|ex| exchange_name(*ex).to_string());
+                (asset.clone(), venues.collect())
+            })
+            .collect();
+        if assets == self.assets && venues == self.venues {
+            return false;
+        }
+        self.assets = assets;
+        self.venues = venues;
+        true
+    }
+
+    fn write(&mut self, record: &Record) -> io::Result<()> {
+        if self.written >= self.max_bytes || self.opened.elapsed() >= self.max_age {
+            self.rotate()?;
+        }
+        self.write_line(record)
+    }
+
+    // Finish the current file and start the next, named after the time it was started
+    fn rotate(&mut self) -> io::Result<()> {
+        if let Some(mut file) = self.file.take() {
+            file.flush()?;
+        }
+        let now = Utc::now();
+        let path = self
+            .dir
+            .join(format!("{}.jsonl", now.format("%Y%m%d-%H%M%S%.3f")));
+        tracing::info!("Recording to {}", path.display());
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        self.file = Some(BufWriter::new(file));
+        self.written = 0;
+        self.opened = Instant::now();
+        let header = Header {
+            version: FORMAT_VERSION,
+            started: now.timestamp_micros(),
+            assets: self.assets.clone(),
+            venues: self.venues.clone(),
+        };
+        self.write_line(&header)
+    }
+
+    fn write_line<T: Serialize>(&mut self, value: &T) -> io::Result<()> {
+        let mut line = serde_json::to_vec(value)?;
+        line.push(b'\n');
+        if let Some(file) = self.file.as_mut() {
+            file.write_all(&line)?;
+            self.written += line.len() as u64;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    fn venues(
+        tickers: &HashMap<String, HashMap<Exchange, String>>,
+    ) -> HashMap<String, Vec<Exchange>> {
+        tickers
+            .iter()
+            .map(|(asset, markets)| (asset.clone(), markets.keys().copied().collect()))
+            .collect()
+    }
+
+    #[test]
+    pub fn rotates_files() {
+        let dir = std::env::temp_dir().join(format!("agg-ws-term-record-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let config = RecordConfig {
+            dir: dir.clone(),
+            max_file_mb: 1,
+            max_file_secs: 3_600,
+        };
+        let tickers = HashMap::from([(
+            "BTC".to_string(),
+            HashMap::from([(Exchange::Kraken, "XBT/USD".to_string())]),
+        )]);
+        let recorder = Recorder::new(&config, &tickers, &venues(&tickers)).unwrap();
+        let record = Record {
+            t: 1_700_000_000_000_000,
+            ex: "kraken".to_string(),
+            ch: "book".to_string(),
+            m: "XBT/USD".to_string(),
+            resp: RecordedResp::Book {
+                bids: vec![(dec!(100), dec!(1.5))],
+                asks: vec![(dec!(101), dec!(2))],
+            },
+        };
+        recorder.write(&record);
+        // Past the size limit the next record starts a new file
+        recorder.files.lock().unwrap().written = 1_000_000;
+        std::thread::sleep(Duration::from_millis(2));
+        recorder.write(&record);
+        recorder.flush();
+
+        let mut paths: Vec<PathBuf> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|e| e.unwrap().path())
+            .collect();
+        paths.sort();
+        assert_eq!(paths.len(), 2);
+        for path in paths.iter() {
+            let contents = fs::read_to_string(path).unwrap();
+            let mut lines = contents.lines();
+            let header: Header = serde_json::from_str(lines.next().unwrap()).unwrap();
+            assert_eq!(header.version, FORMAT_VERSION);
+            assert_eq!(header.assets["BTC"]["kraken"], "XBT/USD");
+            let read: Record = serde_json::from_str(lines.next().unwrap()).unwrap();
+            assert_eq!(read, record);
+            assert!(lines.next().is_none());
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    pub fn rotates_on_asset_change() {
+        let dir = std::env::temp_dir().join(format!("agg-ws-term-assets-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let config = RecordConfig {
+            dir: dir.clone(),
+            max_file_mb: 1,
+            max_file_secs: 3_600,
+        };
+        let mut tickers = HashMap::from([(
+            "BTC".to_string(),
+            HashMap::from([(Exchange::Kraken, "XBT/USD".to_string())]),
+        )]);
+        let recorder = Recorder::new(&config, &tickers, &venues(&tickers)).unwrap();
+        // The same assets keep the current file
+        recorder.set_assets(&tickers, &venues(&tickers));
+        std::thread::sleep(Duration::from_millis(2));
+        tickers.insert(
+            "SOL".to_string(),
+            HashMap::from([(Exchange::Gdax, "SOL-USD".to_string())]),
+        );
+        recorder.set_assets(&tickers, &venues(&tickers));
+        recorder.flush();
+
+        let mut paths: Vec<PathBuf> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|e| e.unwrap().path())
+            .collect();
+        paths.sort();
+        let headers: Vec<Header> = paths
+            .iter()
+            .map(|p| {
+                let contents = fs::read_to_string(p).unwrap();
+                serde_json::from_str(contents.lines().next().unwrap()).unwrap()
+            })
+            .collect();
+        assert_eq!(headers.len(), 2);
+        assert!(!headers[0].assets.contains_key("SOL"));
+        assert_eq!(headers[1].assets["SOL"]["coinbase"], "SOL-USD");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,469 @@
+use std::{
+    collections::VecDeque,
+    fs,
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+use agg_ws::{
+    client::{Channel, ChannelType},
+    trades::Trade,
+};
+use anyhow::{anyhow, bail, Context};
+use async_trait::async_trait;
+use chrono::{DateTime, NaiveTime, TimeZone, Utc};
+use futures::future::{self, BoxFuture, FutureExt};
+use tokio::{sync::mpsc, time};
+
+use crate::{
+    config::parse_exchange,
+    feed::{MarketData, Message, Response, VenueBook},
+    record::{Header, Record, RecordedResp, FORMAT_VERSION},
+};
+
+/// Playback speeds stepped through with `+` and `-`.
+pub const SPEEDS: [f64; 8] = [0.25, 0.5, 1.0, 2.0, 5.0, 10.0, 25.0, 50.0];
+const NORMAL_SPEED: usize = 2;
+
+/// Playback controls sent from the UI.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReplayControl {
+    TogglePause,
+    Faster,
+    Slower,
+    /// Pause and play the next message
+    Step,
+    /// Move to a recording time in microseconds since the epoch
+    JumpTo(i64),
+}
+
+/// Where playback is, for the timeline. Times are microseconds since the epoch.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ReplayStatus {
+    pub start: i64,
+    pub end: i64,
+    pub position: i64,
+    pub speed: f64,
+    pub paused: bool,
+}
+
+/// Messages to apply at once after a jump or step. `reset` is set when the jump went backwards
+/// and market data must be cleared before replaying from the start of the recording.
+#[derive(Debug)]
+pub struct Seek {
+    pub reset: bool,
+    pub messages: Vec<Message>,
+}
+
+/// Plays a recording back on its original timing, scaled by the playback speed.
+pub struct Replay {
+    records: Vec<Record>,
+    /// Index of the next record to play
+    next: usize,
+    speed: usize,
+    paused: bool,
+    // Playback runs from recording time `anchor_pos` at wall time `anchor`
+    anchor_pos: i64,
+    anchor: Instant,
+    // Wall time standing in for the start of the recording on the engine clock
+    origin: Instant,
+    acks_tx: mpsc::UnboundedSender<Message>,
+    acks_rx: mpsc::UnboundedReceiver<Message>,
+}
+
+impl Replay {
+    /// Load one or more recording files, e.g. the files of a rotated session, merging the assets
+    /// in their headers.
+    pub fn load(paths: &[PathBuf]) -> anyhow::Result<(Header, Replay)> {
+        let mut header: Option<Header> = None;
+        let mut records = Vec::new();
+        for path in paths.iter() {
+            let contents = fs::read_to_string(path)
+                .with_context(|| format!("failed to read recording {}", path.display()))?;
+            let mut lines = contents.lines().enumerate();
+            let file_header: Header = match lines.next() {
+                Some((_, line)) => serde_json::from_str(line)
+                    .with_context(|| format!("{}: invalid header", path.display()))?,
+                None => bail!("{}: empty recording", path.display()),
+            };
+            if file_header.version != FORMAT_VERSION {
+                bail!(
+                    "{}: unsupported recording version {}",
+                    path.display(),
+                    file_header.version
+                );
+            }
+            for (i, line) in lines {
+                let record: Record = serde_json::from_str(line).with_context(|| {
+                    format!("{} line {}: invalid record", path.display(), i + 1)
+                })?;
+                message(&record).with_context(|| format!("{} line {}", path.display(), i + 1))?;
+                records.push(record);
+            }
+            match header.as_mut() {
+                Some(h) => {
+                    h.started = h.started.min(file_header.started);
+                    h.assets.extend(file_header.assets);
+                    h.venues.extend(file_header.venues);
+                }
+                None => header = Some(file_header),
+            }
+        }
+        let header = header.ok_or_else(|| anyhow!("no recording given"))?;
+        if records.is_empty() {
+            bail!("recording has no messages");
+        }
+        records.sort_by_key(|r| r.t);
+        let now = Instant::now();
+        let replay = Replay::new(records, now);
+        Ok((header, replay))
+    }
+
+    fn new(records: Vec<Record>, now: Instant) -> Replay {
+        let (acks_tx, acks_rx) = mpsc::unbounded_channel();
+        Replay {
+            anchor_pos: records[0].t,
+            records,
+            next: 0,
+            speed: NORMAL_SPEED,
+            paused: false,
+            anchor: now,
+            origin: now,
+            acks_tx,
+            acks_rx,
+        }
+    }
+
+    fn start(&self) -> i64 {
+        self.records[0].t
+    }
+
+    fn end(&self) -> i64 {
+        self.records[self.records.len() - 1].t
+    }
+
+    // Recording time playback has reached
+    fn position(&self, now: Instant) -> i64 {
+        if self.paused {
+            return self.anchor_pos;
+        }
+        let elapsed = now.saturating_duration_since(self.anchor).as_micros() as f64;
+        (self.anchor_pos + (elapsed * SPEEDS[self.speed]) as i64).min(self.end())
+    }
+
+    // Restart the playback clock from the current position, before changing speed or pausing
+    fn rebase(&mut self, now: Instant) {
+        self.anchor_pos = self.position(now);
+        self.anchor = now;
+    }
+
+    pub fn status(&self) -> ReplayStatus {
+        ReplayStatus {
+            start: self.start(),
+            end: self.end(),
+            position: self.position(Instant::now()),
+            speed: SPEEDS[self.speed],
+            paused: self.paused,
+        }
+    }
+
+    // Acknowledge a subscribe or unsubscribe request as the exchange would have
+    fn ack(&self, channel: Channel, resp: Response) {
+        let _ = self.acks_tx.send(Message {
+            channel,
+            resp,
+            received_at: Utc::now(),
+        });
+    }
+
+    // Wall time the next record is due, None when paused or at the end
+    fn due(&self) -> Option<Instant> {
+        if self.paused {
+            return None;
+        }
+        let record = self.records.get(self.next)?;
+        let wait = (record.t - self.anchor_pos).max(0) as f64 / SPEEDS[self.speed];
+        Some(self.anchor + Duration::from_micros(wait as u64))
+    }
+
+    fn play_next(&mut self) -> Option<Message> {
+        let record = self.records.get(self.next)?;
+        self.next += 1;
+        message(record).ok()
+    }
+
+    pub fn control(&mut self, control: ReplayControl) -> Option<Seek> {
+        let now = Instant::now();
+        self.rebase(now);
+        match control {
+            ReplayControl::TogglePause => self.paused = !self.paused,
+            ReplayControl::Faster => self.speed = (self.speed + 1).min(SPEEDS.len() - 1),
+            ReplayControl::Slower => self.speed = self.speed.saturating_sub(1),
+            ReplayControl::Step => {
+                self.paused = true;
+                let record = self.records.get(self.next)?;
+                self.anchor_pos = record.t;
+                let messages = self.play_next().into_iter().collect();
+                return Some(Seek {
+                    reset: false,
+                    messages,
+                });
+            }
+            ReplayControl::JumpTo(t) => return Some(self.jump(t.clamp(self.start(), self.end()))),
+        }
+        None
+    }
+
+    // Play every record up to `t` at once, from the start if `t` is behind the last one played
+    fn jump(&mut self, t: i64) -> Seek {
+        let reset = self.next > 0 && self.records[self.next - 1].t > t;
+        if reset {
+            self.next = 0;
+        }
+        let mut messages = Vec::new();
+        while self.records.get(self.next).is_some_and(|r| r.t <= t) {
+            messages.extend(self.play_next());
+        }
+        self.anchor_pos = t;
+        Seek { reset, messages }
+    }
+}
+
+/// Subscriptions are acknowledged straight away and requests for the current tape or book are
+/// ignored, only what was received is played back.
+#[async_trait]
+impl MarketData for Replay {
+    fn subscribe(&self, channel: Channel) -> BoxFuture<'static, Result<(), String>> {
+        self.ack(channel, Response::Subscribed);
+        future::ready(Ok(())).boxed()
+    }
+
+    async fn unsubscribe(&self, channel: Channel) -> Result<(), String> {
+        self.ack(channel, Response::Unsubscribed);
+        Ok(())
+    }
+
+    async fn get_tape(&self, _channel: Channel) -> Result<(), String> {
+        Ok(())
+    }
+
+    async fn get_book(&self, _channel: Channel) -> Result<(), String> {
+        Ok(())
+    }
+
+    /// The next acknowledgement or recorded message once it is due.
+    async fn recv(&mut self) -> Option<Message> {
+        let due = self.due();
+        tokio::select! {
+            ack = self.acks_rx.recv() => return ack,
+            _ = time::sleep_until(due.unwrap_or_else(Instant::now).into()), if due.is_some() => {}
+        }
+        self.play_next()
+    }
+
+    /// The recording time as an instant, so ages and durations follow playback.
+    fn now(&self) -> Instant {
+        let offset = self.position(Instant::now()) - self.start();
+        self.origin + Duration::from_micros(offset.max(0) as u64)
+    }
+
+    fn replay_status(&self) -> Option<ReplayStatus> {
+        Some(self.status())
+    }
+
+    fn replay_control(&mut self, control: ReplayControl) -> Option<Seek> {
+        self.control(control)
+    }
+}
+
+/// Parse a jump target: a UTC time of day on the day of the current position, `HH:MM[:SS]`, or
+/// an offset from it such as `+30s`, `-5m` or `+1h`, seconds if no unit is given.
+pub fn parse_jump(s: &str, status: &ReplayStatus) -> anyhow::Result<i64> {
+    let s = s.trim();
+    let usage = || anyhow!("expected HH:MM[:SS] or an offset such as +30s, -5m or +1h");
+    if let Some(offset) = s.strip_prefix('+').or_else(|| s.strip_prefix('-')) {
+        let (n, unit) = match offset.find(|c: char| !c.is_ascii_digit()) {
+            Some(i) => offset.split_at(i),
+            None => (offset, "s"),
+        };
+        let n: i64 = n.parse().map_err(|_| usage())?;
+        let secs = match unit {
+            "s" => Some(n),
+            "m" => n.checked_mul(60),
+            "h" => n.checked_mul(3_600),
+            _ => return Err(usage()),
+        };
+        // Offsets too large to represent are as unusable as malformed ones
+        let micros = secs.and_then(|s| s.checked_mul(1_000_000));
+        let target = micros.and_then(|m| {
+            if s.starts_with('-') {
+                status.position.checked_sub(m)
+            } else {
+                status.position.checked_add(m)
+            }
+        });
+        return target.ok_or_else(usage);
+    }
+    let time = NaiveTime::parse_from_str(s, "%H:%M:%S")
+        .or_else(|_| NaiveTime::parse_from_str(s, "%H:%M"))
+        .map_err(|_| usage())?;
+    let day = to_datetime(status.position)
+        .ok_or_else(|| anyhow!("invalid position"))?
+        .date_naive();
+    Ok(Utc
+        .from_utc_datetime(&day.and_time(time))
+        .timestamp_micros())
+}
+
+/// A recording timestamp as a UTC datetime.
+pub fn to_datetime(micros: i64) -> Option<DateTime<Utc>> {
+    Utc.timestamp_micros(micros).single()
+}
+
+// The message a record was made from
+fn message(record: &Record) -> anyhow::Result<Message> {
+    let exchange =
+        parse_exchange(&record.ex).ok_or_else(|| anyhow!("unknown exchange {}", record.ex))?;
+    let channel = Channel {
+        exchange,
+        channel: match record.ch.as_str() {
+            "tape" => ChannelType::Tape,
+            "book" => ChannelType::Book,
+            ch => bail!("unknown channel {}", ch),
+        },
+        market: record.m.clone(),
+    };
+    let resp = match &record.resp {
+        RecordedResp::Subscribed => Response::Subscribed,
+        RecordedResp::Unsubscribed => Response::Unsubscribed,
+        RecordedResp::Tape(trades) => {
+            let mut tape = VecDeque::with_capacity(trades.len());
+            for t in trades.iter() {
+                tape.push_back(Trade {
+                    size: t.s.clone(),
+                    price: t.p.clone(),
+                    dt: to_datetime(t.t).ok_or_else(|| anyhow!("invalid trade time {}", t.t))?,
+                    exchange,
+                });
+            }
+            Response::Tape(tape)
+        }
+        RecordedResp::Book { bids, asks } => Response::Book(VenueBook {
+            bids: bids.iter().cloned().collect(),
+            asks: asks.iter().cloned().collect(),
+        }),
+        RecordedResp::Last => Response::Last,
+    };
+    Ok(Message {
+        channel,
+        resp,
+        received_at: to_datetime(record.t)
+            .ok_or_else(|| anyhow!("invalid receive time {}", record.t))?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    fn book(t: i64, bid: rust_decimal::Decimal) -> Record {
+        Record {
+            t,
+            ex: "kraken".to_string(),
+            ch: "book".to_string(),
+            m: "XBT/USD".to_string(),
+            resp: RecordedResp::Book {
+                bids: vec![(bid, dec!(1))],
+                asks: vec![(bid + dec!(1), dec!(1))],
+            },
+        }
+    }
+
+    fn bid(msg: &Message) -> rust_decimal::Decimal {
+        match &msg.resp {
+            Response::Book(b) => *b.bids.keys().next().unwrap(),
+            _ => panic!("expected a book"),
+        }
+    }
+
+    #[test]
+    pub fn controls() {
+        let start = 1_700_000_000_000_000;
+        let records = (0..5)
+            .map(|i| {
+                book(
+                    start + i * 1_000_000,
+                    dec!(100) + rust_decimal::Decimal::from(i),
+                )
+            })
+            .collect();
+        let mut replay = Replay::new(records, Instant::now());
+        replay.control(ReplayControl::TogglePause);
+        assert!(replay.due().is_none());
+
+        let seek = replay.control(ReplayControl::Step).unwrap();
+        assert!(!seek.reset);
+        assert_eq!(bid(&seek.messages[0]), dec!(100));
+        assert_eq!(replay.status().position, start);
+
+        // Forward plays everything up to the target, backward starts over
+        let seek = replay
+            .control(ReplayControl::JumpTo(start + 3_000_000))
+            .unwrap();
+        assert!(!seek.reset);
+        assert_eq!(seek.messages.len(), 3);
+        assert_eq!(bid(&seek.messages[2]), dec!(103));
+        let seek = replay
+            .control(ReplayControl::JumpTo(start + 1_500_000))
+            .unwrap();
+        assert!(seek.reset);
+        assert_eq!(seek.messages.len(), 2);
+        assert_eq!(replay.status().position, start + 1_500_000);
+
+        replay.control(ReplayControl::Faster);
+        replay.control(ReplayControl::TogglePause);
+        let status = replay.status();
+        assert_eq!(status.speed, 2.0);
+        assert!(!status.paused);
+        // The next record is half a second of recording away, a quarter second at 2x
+        let wait = replay.due().unwrap() - replay.anchor;
+        assert_eq!(wait, Duration::from_millis(250));
+    }
+
+    #[test]
+    pub fn jump_targets() {
+        let status = ReplayStatus {
+            start: 0,
+            end: 0,
+            // 2023-11-14 22:13:20 UTC
+            position: 1_700_000_000_000_000,
+            speed: 1.0,
+            paused: true,
+        };
+        assert_eq!(
+            parse_jump("+30s", &status).unwrap(),
+            status.position + 30_000_000
+        );
+        assert_eq!(
+            parse_jump("-2m", &status).unwrap(),
+            status.position - 120_000_000
+        );
+        assert_eq!(
+            parse_jump("+5", &status).unwrap(),
+            status.position + 5_000_000
+        );
+        assert_eq!(
+            parse_jump("22:00", &status).unwrap(),
+            status.position - 800_000_000
+        );
+        assert_eq!(
+            parse_jump("22:13:21", &status).unwrap(),
+            status.position + 1_000_000
+        );
+        assert!(parse_jump("+5d", &status).is_err());
+        assert!(parse_jump("+9223372036854775807h", &status).is_err());
+        assert!(parse_jump("-9223372036855s", &status).is_err());
+        assert!(parse_jump("soon", &status).is_err());
+    }
+}
@@ -0,0 +1,270 @@
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use agg_ws::client::{Channel, ChannelType};
+
+use crate::config::SubscriptionConfig;
+
+/// Lifecycle of a single channel subscription.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SubState {
+    /// Waiting to be requested from the client
+    Queued,
+    /// Subscribe request sent, waiting for the Subscribed acknowledgement
+    Requesting,
+    Subscribed,
+    /// Subscribe request returned an error, retried with backoff until the attempts run out
+    Failed,
+    /// Unsubscribe request sent, waiting for the Unsubscribed acknowledgement
+    Unsubscribing,
+}
+
+impl SubState {
+    pub fn as_display(&self) -> &'static str {
+        match self {
+            Self::Queued => "Queued",
+            Self::Requesting => "Requesting",
+            Self::Subscribed => "Subscribed",
+            Self::Failed => "Failed",
+            Self::Unsubscribing => "Unsubscribing",
+        }
+    }
+}
+
+pub fn channel_type_display(channel_type: &ChannelType) -> &'static str {
+    match channel_type {
+        ChannelType::Tape => "Tape",
+        ChannelType::Book => "Book",
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SubEntry {
+    pub state: SubState,
+    /// Failed subscribe requests since the last success
+    pub attempts: u32,
+    pub retry_at: Option<Instant>,
+    pub error: Option<String>,
+}
+
+impl SubEntry {
+    fn new(state: SubState) -> Self {
+        SubEntry {
+            state,
+            attempts: 0,
+            retry_at: None,
+            error: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    pub base: Duration,
+    pub max: Duration,
+    pub max_attempts: u32,
+}
+
+impl RetryPolicy {
+    /// Delay before the retry following the `attempts`th failure, doubling each time.
+    pub fn backoff(&self, attempts: u32) -> Duration {
+        let factor = 2u32.saturating_pow(attempts.saturating_sub(1));
+        self.base.saturating_mul(factor).min(self.max)
+    }
+}
+
+impl From<&SubscriptionConfig> for RetryPolicy {
+    fn from(config: &SubscriptionConfig) -> Self {
+        RetryPolicy {
+            base: Duration::from_millis(config.retry_base_ms),
+            max: Duration::from_millis(config.retry_max_ms),
+            max_attempts: config.max_attempts,
+        }
+    }
+}
+
+/// Subscription state of every channel the app has asked for, keyed by channel so that
+/// acknowledgements are filed against the channel they belong to.
+#[derive(Debug)]
+pub struct Subscriptions {
+    entries: HashMap<Channel, SubEntry>,
+    retry: RetryPolicy,
+}
+
+impl Subscriptions {
+    pub fn new(retry: RetryPolicy) -> Self {
+        Subscriptions {
+            entries: HashMap::new(),
+            retry,
+        }
+    }
+
+    pub fn state(&self, channel: &Channel) -> Option<SubState> {
+        self.entries.get(channel).map(|e| e.state)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&Channel, &SubEntry)> {
+        self.entries.iter()
+    }
+
+    // Queued, or failed with a retry that is due
+    fn is_due(&self, entry: &SubEntry, now: Instant) -> bool {
+        match entry.state {
+            SubState::Queued => true,
+            SubState::Failed => matches!(entry.retry_at, Some(t) if t <= now),
+            _ => false,
+        }
+    }
+
+    pub fn has_due(&self, now: Instant) -> bool {
+        self.entries.values().any(|e| self.is_due(e, now))
+    }
+
+    /// Queue a channel unless it is already queued, in flight or subscribed.
+    pub fn queue(&mut self, channel: &Channel) -> bool {
+        match self.state(channel) {
+            Some(SubState::Queued | SubState::Requesting | SubState::Subscribed) => false,
+            Some(SubState::Failed | SubState::Unsubscribing) | None => {
+                self.entries
+                    .insert(channel.clone(), SubEntry::new(SubState::Queued));
+                true
+            }
+        }
+    }
+
+    /// Queue a channel again with its attempts reset, unless it is subscribed or on its way out.
+    pub fn resubscribe(&mut self, channel: &Channel) -> bool {
+        match self.state(channel) {
+            Some(SubState::Subscribed | SubState::Unsubscribing) => false,
+            _ => {
+                self.entries
+                    .insert(channel.clone(), SubEntry::new(SubState::Queued));
+                true
+            }
+        }
+    }
+
+    /// Take the next queued or due channel and mark it as being requested.
+    pub fn next_due(&mut self, now: Instant) -> Option<Channel> {
+        let channel = self
+            .entries
+            .iter()
+            .find(|(_, e)| self.is_due(e, now))
+            .map(|(c, _)| c.clone())?;
+        let entry = self.entries.get_mut(&channel).unwrap();
+        entry.state = SubState::Requesting;
+        entry.retry_at = None;
+        Some(channel)
+    }
+
+    /// Record a failed subscribe request and schedule the retry, if any attempts are left.
+    pub fn request_failed(&mut self, channel: &Channel, error: String, now: Instant) {
+        let entry = self
+            .entries
+            .entry(channel.clone())
+            .or_insert_with(|| SubEntry::new(SubState::Failed));
+        entry.state = SubState::Failed;
+        entry.attempts += 1;
+        entry.error = Some(error);
+        entry.retry_at = if entry.attempts < self.retry.max_attempts {
+            Some(now + self.retry.backoff(entry.attempts))
+        } else {
+            None
+        };
+    }
+
+    pub fn subscribed(&mut self, channel: &Channel) {
+        self.entries
+            .insert(channel.clone(), SubEntry::new(SubState::Subscribed));
+    }
+
+    /// Drop a channel that has not reached the exchange yet. Returns true if the channel is
+    /// subscribed and needs an unsubscribe request instead.
+    pub fn dequeue(&mut self, channel: &Channel) -> bool {
+        match self.state(channel) {
+            Some(SubState::Queued | SubState::Failed) => {
+                self.entries.remove(channel);
+                false
+            }
+            Some(SubState::Subscribed) => true,
+            // Requests in flight are released once acknowledged
+            Some(SubState::Requesting | SubState::Unsubscribing) | None => false,
+        }
+    }
+
+    pub fn unsubscribing(&mut self, channel: &Channel) {
+        self.entries
+            .insert(channel.clone(), SubEntry::new(SubState::Unsubscribing));
+    }
+
+    /// Release a channel once its unsubscribe is acknowledged. Returns false, leaving the entry
+    /// alone, if the channel was queued again while the request was in flight.
+    pub fn unsubscribed(&mut self, channel: &Channel) -> bool {
+        if self.state(channel) != Some(SubState::Unsubscribing) {
+            return false;
+        }
+        self.entries.remove(channel);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use agg_ws::client::{ChannelType, Exchange};
+
+    fn channel(market: &str) -> Channel {
+        Channel {
+            exchange: Exchange::Kraken,
+            channel: ChannelType::Book,
+            market: market.to_string(),
+        }
+    }
+
+    fn retry() -> RetryPolicy {
+        RetryPolicy {
+            base: Duration::from_millis(500),
+            max: Duration::from_secs(4),
+            max_attempts: 4,
+        }
+    }
+
+    #[test]
+    pub fn lifecycle() {
+        let mut subs = Subscriptions::new(retry());
+        let now = Instant::now();
+        let btc = channel("XBT/USD");
+        assert!(subs.queue(&btc));
+        assert!(!subs.queue(&btc));
+        assert_eq!(subs.next_due(now), Some(btc.clone()));
+        assert_eq!(subs.state(&btc), Some(SubState::Requesting));
+        assert_eq!(subs.next_due(now), None);
+        subs.subscribed(&btc);
+        assert!(!subs.queue(&btc));
+        assert!(subs.dequeue(&btc));
+        subs.unsubscribing(&btc);
+        assert_eq!(subs.state(&btc), Some(SubState::Unsubscribing));
+        assert!(subs.unsubscribed(&btc));
+        assert_eq!(subs.state(&btc), None);
+
+        // A late acknowledgement does not release a channel queued again in the meantime
+        subs.queue(&btc);
+        subs.next_due(now);
+        subs.subscribed(&btc);
+        subs.unsubscribing(&btc);
+        assert!(subs.queue(&btc));
+        assert!(!subs.unsubscribed(&btc));
+        assert_eq!(subs.state(&btc), Some(SubState::Queued));
+    }
+
+    #[test]
+    pub fn backoff_doubles_up_to_max() {
+        let retry = retry();
+        assert_eq!(retry.backoff(1), Duration::from_millis(500));
+        assert_eq!(retry.backoff(2), Duration::from_millis(1000));
+        assert_eq!(retry.backoff(3), Duration::from_millis(2000));
+        assert_eq!(retry.backoff(5), Duration::from_secs(4));
+    }
+}
@@ -0,0 +1,297 @@
+use std::str::FromStr;
+
+use agg_ws::client::Exchange;
+use anyhow::{anyhow, bail};
+use rust_decimal::Decimal;
+
+use crate::{symbols::Instrument, tape::Side, AggBook, Level};
+
+/// What an order size is measured in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Unit {
+    Base,
+    Quote,
+}
+
+/// A market order to price against the book, e.g. `buy 50` or `sell 10000 USD`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SweepOrder {
+    pub side: Side,
+    pub amount: Decimal,
+    pub unit: Unit,
+}
+
+impl SweepOrder {
+    /// Parse an order of the form `buy|sell SIZE [BASE|QUOTE]`, sized in the base currency unless
+    /// the instrument's quote currency is given.
+    pub fn parse(s: &str, instrument: &Instrument) -> anyhow::Result<SweepOrder> {
+        let usage = || {
+            anyhow!(
+                "expected buy|sell SIZE [{}|{}]",
+                instrument.base,
+                instrument.quote
+            )
+        };
+        let mut tokens = s.split_whitespace();
+        let side = match tokens.next().map(|t| t.to_lowercase()).as_deref() {
+            Some("buy" | "b") => Side::Buy,
+            Some("sell" | "s") => Side::Sell,
+            _ => return Err(usage()),
+        };
+        let amount = tokens.next().ok_or_else(usage)?;
+        let amount = match Decimal::from_str(amount) {
+            Ok(a) if a > Decimal::ZERO => a,
+            _ => bail!("size {} must be a positive number", amount),
+        };
+        let unit = match tokens.next() {
+            None => Unit::Base,
+            Some(u) if u.eq_ignore_ascii_case(&instrument.base) => Unit::Base,
+            Some(u) if u.eq_ignore_ascii_case(&instrument.quote) => Unit::Quote,
+            Some(u) => bail!(
+                "unknown unit {}, expected {} or {}",
+                u,
+                instrument.base,
+                instrument.quote
+            ),
+        };
+        if tokens.next().is_some() {
+            return Err(usage());
+        }
+        Ok(SweepOrder { side, amount, unit })
+    }
+
+    pub fn describe(&self, instrument: &Instrument) -> String {
+        format!(
+            "{} {} {}",
+            match self.side {
+                Side::Buy => "Buy",
+                Side::Sell => "Sell",
+            },
+            self.amount.normalize(),
+            match self.unit {
+                Unit::Base => &instrument.base,
+                Unit::Quote => &instrument.quote,
+            }
+        )
+    }
+}
+
+/// Result of walking an order through the book.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Fill {
+    /// Base size filled
+    pub filled: Decimal,
+    /// Quote amount paid or received
+    pub notional: Decimal,
+    /// Price of the last level reached
+    pub worst: Option<Decimal>,
+    /// Base size filled on each venue, in the order first reached
+    pub venues: Vec<(Exchange, Decimal)>,
+    /// False if the book ran out before the order was filled
+    pub complete: bool,
+}
+
+impl Fill {
+    pub fn average(&self) -> Option<Decimal> {
+        self.notional.checked_div(self.filled)
+    }
+
+    /// How much worse the average price is than `mid`, in basis points.
+    pub fn slippage_bps(&self, side: Side, mid: Decimal) -> Option<Decimal> {
+        let avg = self.average()?;
+        let diff = match side {
+            Side::Buy => avg - mid,
+            Side::Sell => mid - avg,
+        };
+        diff.checked_div(mid).map(|d| d * Decimal::from(10_000))
+    }
+}
+
+/// An order priced against the aggregated book and against each of its venues alone.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SweepReport {
+    pub order: SweepOrder,
+    /// Midpoint of the aggregated book
+    pub mid: Option<Decimal>,
+    pub aggregate: Fill,
+    pub venues: Vec<(Exchange, Fill)>,
+}
+
+impl SweepReport {
+    pub fn new(book: &AggBook, order: &SweepOrder, exchanges: &[Exchange]) -> SweepReport {
+        let mid = match (book.bids.keys().next_back(), book.asks.keys().next()) {
+            (Some(bid), Some(ask)) => Some((bid + ask) / Decimal::TWO),
+            _ => None,
+        };
+        SweepReport {
+            order: order.clone(),
+            mid,
+            aggregate: sweep(book, order, None),
+            venues: exchanges
+                .iter()
+                .map(|ex| (*ex, sweep(book, order, Some(*ex))))
+                .collect(),
+        }
+    }
+}
+
+/// Walk the asks for a buy or the bids for a sell, best price first, until the order is filled.
+/// With `venue` set only that venue's share of each level is taken, as if the whole order were
+/// sent to it alone. Several venues at one price are filled in proportion to their size.
+pub fn sweep(book: &AggBook, order: &SweepOrder, venue: Option<Exchange>) -> Fill {
+    match order.side {
+        Side::Buy => walk(book.asks.iter(), order, venue),
+        Side::Sell => walk(book.bids.iter().rev(), order, venue),
+    }
+}
+
+fn walk<'a>(
+    levels: impl Iterator<Item = (&'a Decimal, &'a Level)>,
+    order: &SweepOrder,
+    venue: Option<Exchange>,
+) -> Fill {
+    let mut fill = Fill::default();
+    let mut remaining = order.amount;
+    for (price, level) in levels {
+        let size = match venue {
+            Some(ex) => venue_size(level, ex),
+            None => level.size,
+        };
+        if size <= Decimal::ZERO {
+            continue;
+        }
+        let wanted = match order.unit {
+            Unit::Base => remaining,
+            Unit::Quote => remaining / price,
+        };
+        let taken = wanted.min(size);
+        fill.filled += taken;
+        fill.notional += taken * price;
+        fill.worst = Some(*price);
+        for (ex, venue_sz) in level.venues.iter() {
+            if venue.is_some_and(|v| v != *ex) {
+                continue;
+            }
+            let share = taken * venue_sz / size;
+            match fill.venues.iter_mut().find(|(e, _)| e == ex) {
+                Some((_, s)) => *s += share,
+                None => fill.venues.push((*ex, share)),
+            }
+        }
+        if taken == wanted {
+            fill.complete = true;
+            break;
+        }
+        remaining -= match order.unit {
+            Unit::Base => taken,
+            Unit::Quote => taken * price,
+        };
+    }
+    fill
+}
+
+fn venue_size(level: &Level, exchange: Exchange) -> Decimal {
+    level
+        .venues
+        .iter()
+        .find(|(ex, _)| *ex == exchange)
+        .map(|(_, s)| *s)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+    use std::collections::BTreeMap;
+
+    fn book() -> AggBook {
+        let mut book = AggBook::new();
+        book.merge(
+            Exchange::Kraken,
+            &BTreeMap::from([(dec!(99), dec!(2))]),
+            &BTreeMap::from([(dec!(101), dec!(1)), (dec!(102), dec!(2))]),
+        );
+        book.merge(
+            Exchange::Gdax,
+            &BTreeMap::from([(dec!(99), dec!(2))]),
+            &BTreeMap::from([(dec!(101), dec!(3)), (dec!(104), dec!(10))]),
+        );
+        book
+    }
+
+    #[test]
+    pub fn parse_order() {
+        let sol = Instrument::new("SOL", "USD");
+        let order = SweepOrder::parse("buy 50", &sol).unwrap();
+        assert_eq!(
+            (order.side, order.amount, order.unit),
+            (Side::Buy, dec!(50), Unit::Base)
+        );
+        let order = SweepOrder::parse("S 2500.5 usd", &sol).unwrap();
+        assert_eq!(
+            (order.side, order.amount, order.unit),
+            (Side::Sell, dec!(2500.5), Unit::Quote)
+        );
+        assert_eq!(order.describe(&sol), "Sell 2500.5 USD");
+        let err = SweepOrder::parse("buy 5 eur", &sol).unwrap_err();
+        assert_eq!(err.to_string(), "unknown unit eur, expected SOL or USD");
+        let err = SweepOrder::parse("buy -5", &sol).unwrap_err();
+        assert_eq!(err.to_string(), "size -5 must be a positive number");
+        assert!(SweepOrder::parse("hold 5", &sol).is_err());
+    }
+
+    #[test]
+    pub fn sweeps_book() {
+        let book = book();
+        let buy = |amount, unit| SweepOrder {
+            side: Side::Buy,
+            amount,
+            unit,
+        };
+
+        // 4 at 101 split 1:3, then 2 at 102 on Kraken
+        let report = SweepReport::new(
+            &book,
+            &buy(dec!(6), Unit::Base),
+            &[Exchange::Kraken, Exchange::Gdax],
+        );
+        assert_eq!(report.mid, Some(dec!(100)));
+        let fill = &report.aggregate;
+        assert!(fill.complete);
+        assert_eq!(fill.notional, dec!(608));
+        assert_eq!(fill.worst, Some(dec!(102)));
+        assert_eq!(
+            fill.venues,
+            vec![(Exchange::Kraken, dec!(3)), (Exchange::Gdax, dec!(3))]
+        );
+        assert_eq!(
+            fill.slippage_bps(Side::Buy, dec!(100)).unwrap().round_dp(2),
+            dec!(133.33)
+        );
+
+        // Kraken alone runs out after 3
+        let (_, kraken) = &report.venues[0];
+        assert!(!kraken.complete);
+        assert_eq!((kraken.filled, kraken.notional), (dec!(3), dec!(305)));
+        // Coinbase fills 3 at 101 and 3 at 104
+        let (_, coinbase) = &report.venues[1];
+        assert_eq!(coinbase.average(), Some(dec!(102.5)));
+        assert_eq!(coinbase.venues, vec![(Exchange::Gdax, dec!(6))]);
+
+        // 404 buys all of 101 then the rest is spent at 102
+        let fill = sweep(&book, &buy(dec!(608), Unit::Quote), None);
+        assert!(fill.complete);
+        assert_eq!(fill.notional, dec!(608));
+        assert_eq!(fill.filled, dec!(6));
+
+        let sell = SweepOrder {
+            side: Side::Sell,
+            amount: dec!(3),
+            unit: Unit::Base,
+        };
+        let fill = sweep(&book, &sell, None);
+        assert_eq!(fill.average(), Some(dec!(99)));
+        assert_eq!(fill.slippage_bps(Side::Sell, dec!(100)), Some(dec!(100)));
+    }
+}
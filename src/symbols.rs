@@ -0,0 +1,124 @@
+use std::fmt;
+
+use agg_ws::client::Exchange;
+use rust_decimal::Decimal;
+
+// Kraken still lists some assets under their legacy ISO 4217-A3 codes
+const KRAKEN_ALIASES: &[(&str, &str)] = &[("BTC", "XBT"), ("DOGE", "XDG")];
+// Hyperliquid lists low priced perps per thousand units, see `units`
+const HYPERLIQUID_ALIASES: &[(&str, &str)] = &[
+    ("PEPE", "kPEPE"),
+    ("SHIB", "kSHIB"),
+    ("BONK", "kBONK"),
+    ("FLOKI", "kFLOKI"),
+];
+
+/// Canonical base/quote pair that an asset is built around, independent of venue naming.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Instrument {
+    pub base: String,
+    pub quote: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VenueType {
+    Spot,
+    Perp,
+}
+
+impl Instrument {
+    pub fn new(base: &str, quote: &str) -> Self {
+        Instrument {
+            base: base.to_uppercase(),
+            quote: quote.to_uppercase(),
+        }
+    }
+
+    /// Parse `BASE` or `BASE/QUOTE`, defaulting the quote to USD.
+    pub fn parse(s: &str) -> Self {
+        match s.split_once('/') {
+            Some((base, quote)) => Self::new(base, quote),
+            None => Self::new(s, "USD"),
+        }
+    }
+
+    /// Market string for this instrument on `exchange`, or None if the venue does not list it.
+    pub fn market(&self, exchange: Exchange) -> Option<String> {
+        match exchange {
+            Exchange::Kraken => Some(format!(
+                "{}/{}",
+                alias(KRAKEN_ALIASES, &self.base),
+                alias(KRAKEN_ALIASES, &self.quote)
+            )),
+            Exchange::Gdax => Some(format!("{}-{}", self.base, self.quote)),
+            // Perps only, all margined in USDC
+            Exchange::Hyperliquid => match self.quote.as_str() {
+                "USD" | "USDC" => Some(alias(HYPERLIQUID_ALIASES, &self.base).to_string()),
+                _ => None,
+            },
+        }
+    }
+}
+
+impl fmt::Display for Instrument {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.base, self.quote)
+    }
+}
+
+impl VenueType {
+    pub fn of(exchange: Exchange) -> Self {
+        match exchange {
+            Exchange::Kraken | Exchange::Gdax => Self::Spot,
+            Exchange::Hyperliquid => Self::Perp,
+        }
+    }
+
+    pub fn as_display(&self) -> &'static str {
+        match self {
+            Self::Spot => "Spot",
+            Self::Perp => "Perp",
+        }
+    }
+}
+
+/// Units of the base asset that one unit of the venue's market stands for, 1000 for Hyperliquid's
+/// per thousand perps. Prices and sizes are converted to single units as they are received so that
+/// every venue's book is aggregated in the same units.
+pub fn units(exchange: Exchange, market: &str) -> Decimal {
+    match exchange {
+        Exchange::Hyperliquid if HYPERLIQUID_ALIASES.iter().any(|(_, m)| *m == market) => {
+            Decimal::ONE_THOUSAND
+        }
+        _ => Decimal::ONE,
+    }
+}
+
+fn alias<'a>(aliases: &[(&str, &'a str)], code: &'a str) -> &'a str {
+    aliases
+        .iter()
+        .find(|(canonical, _)| *canonical == code)
+        .map(|(_, alias)| *alias)
+        .unwrap_or(code)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn markets() {
+        let btc = Instrument::parse("btc");
+        assert_eq!(btc.market(Exchange::Kraken).unwrap(), "XBT/USD");
+        assert_eq!(btc.market(Exchange::Gdax).unwrap(), "BTC-USD");
+        assert_eq!(btc.market(Exchange::Hyperliquid).unwrap(), "BTC");
+        let pepe = Instrument::parse("PEPE");
+        assert_eq!(pepe.market(Exchange::Hyperliquid).unwrap(), "kPEPE");
+        assert_eq!(units(Exchange::Hyperliquid, "kPEPE"), Decimal::ONE_THOUSAND);
+        assert_eq!(units(Exchange::Hyperliquid, "BTC"), Decimal::ONE);
+        assert_eq!(units(Exchange::Gdax, "PEPE-USD"), Decimal::ONE);
+        let eth_btc = Instrument::parse("ETH/BTC");
+        assert_eq!(eth_btc.market(Exchange::Kraken).unwrap(), "ETH/XBT");
+        assert!(eth_btc.market(Exchange::Hyperliquid).is_none());
+    }
+}
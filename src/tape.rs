@@ -0,0 +1,178 @@
+use std::str::FromStr;
+
+use agg_ws::{client::Exchange, trades::Trade};
+use anyhow::{anyhow, bail};
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
+
+/// Aggressor side of a trade.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Side {
+    Buy,
+    Sell,
+}
+
+/// A trade as shown on the tape, parsed and validated once when it is received.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TapeTrade {
+    pub price: Decimal,
+    pub size: Decimal,
+    /// Exchange timestamp
+    pub dt: DateTime<Utc>,
+    /// When the trade reached this app
+    pub received_at: DateTime<Utc>,
+    pub exchange: Exchange,
+    /// Inferred aggressor side, None until there is a book or a previous trade to compare with
+    pub side: Option<Side>,
+}
+
+impl TapeTrade {
+    /// Parse a venue trade, rejecting prices and sizes that are unparseable or not positive.
+    pub fn parse(trade: &Trade, received_at: DateTime<Utc>) -> anyhow::Result<TapeTrade> {
+        let price = parse_positive("price", &trade.price)?;
+        let size = parse_positive("size", &trade.size)?;
+        Ok(TapeTrade {
+            price,
+            size,
+            dt: trade.dt,
+            received_at,
+            exchange: trade.exchange,
+            side: None,
+        })
+    }
+
+    /// The trade in single units of the base asset, for a market quoting `units` at a time.
+    pub fn per_unit(mut self, units: Decimal) -> TapeTrade {
+        self.price /= units;
+        self.size *= units;
+        self
+    }
+}
+
+/// Infer the aggressor side of a trade at `price`. The venue feeds carry no side, so trades above
+/// the venue's mid are buys and below it sells. Trades at the mid, or without a book, fall back to
+/// the tick rule against the previous trade: an uptick is a buy, a downtick a sell and an
+/// unchanged price keeps the previous side.
+pub fn classify(
+    price: Decimal,
+    mid: Option<Decimal>,
+    prev: Option<(Decimal, Option<Side>)>,
+) -> Option<Side> {
+    match mid {
+        Some(mid) if price > mid => return Some(Side::Buy),
+        Some(mid) if price < mid => return Some(Side::Sell),
+        _ => (),
+    }
+    match prev {
+        Some((prev_price, _)) if price > prev_price => Some(Side::Buy),
+        Some((prev_price, _)) if price < prev_price => Some(Side::Sell),
+        Some((_, side)) => side,
+        None => None,
+    }
+}
+
+/// A venue's mid since each of its last two changes, so a trade is classified against the book it
+/// traded into rather than one received after it.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Mids {
+    current: Option<(DateTime<Utc>, Option<Decimal>)>,
+    previous: Option<(DateTime<Utc>, Option<Decimal>)>,
+}
+
+impl Mids {
+    /// Record the mid of a book received at `at`, None if a side is empty.
+    pub fn update(&mut self, at: DateTime<Utc>, mid: Option<Decimal>) {
+        if self.current.is_some_and(|(_, current)| current == mid) {
+            return;
+        }
+        self.previous = self.current.replace((at, mid));
+    }
+
+    /// The mid in force at `dt`. None if both held mids changed after it, leaving the tick rule.
+    pub fn at(&self, dt: DateTime<Utc>) -> Option<Decimal> {
+        [self.current, self.previous]
+            .into_iter()
+            .flatten()
+            .find(|(at, _)| *at <= dt)
+            .and_then(|(_, mid)| mid)
+    }
+}
+
+fn parse_positive(field: &str, s: &str) -> anyhow::Result<Decimal> {
+    let d = Decimal::from_str(s).map_err(|e| anyhow!("invalid {} {:?}: {}", field, s, e))?;
+    if d <= Decimal::ZERO {
+        bail!("{} {} is not positive", field, d);
+    }
+    Ok(d)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use rust_decimal_macros::dec;
+
+    fn trade(price: &str, size: &str) -> Trade {
+        Trade {
+            size: size.to_string(),
+            price: price.to_string(),
+            dt: Utc::now(),
+            exchange: Exchange::Kraken,
+        }
+    }
+
+    #[test]
+    pub fn parse() {
+        let now = Utc::now();
+        let t = TapeTrade::parse(&trade("123214.213", "0.5"), now).unwrap();
+        assert_eq!(t.price, dec!(123214.213));
+        assert_eq!(t.size, dec!(0.5));
+        assert_eq!(t.received_at, now);
+        let err = TapeTrade::parse(&trade("12x.5", "1"), now).unwrap_err();
+        assert!(err.to_string().starts_with("invalid price \"12x.5\""));
+        let err = TapeTrade::parse(&trade("100", "0"), now).unwrap_err();
+        assert_eq!(err.to_string(), "size 0 is not positive");
+        assert!(TapeTrade::parse(&trade("", "1"), now).is_err());
+    }
+
+    #[test]
+    pub fn classify_side() {
+        // Against the mid
+        assert_eq!(
+            classify(dec!(101), Some(dec!(100.5)), None),
+            Some(Side::Buy)
+        );
+        assert_eq!(
+            classify(dec!(100), Some(dec!(100.5)), Some((dec!(99), None))),
+            Some(Side::Sell)
+        );
+        // Tick rule at the mid or without a book
+        let prev = Some((dec!(100), Some(Side::Sell)));
+        assert_eq!(
+            classify(dec!(100.5), Some(dec!(100.5)), prev),
+            Some(Side::Buy)
+        );
+        assert_eq!(classify(dec!(99.5), None, prev), Some(Side::Sell));
+        assert_eq!(classify(dec!(100), None, prev), Some(Side::Sell));
+        assert_eq!(classify(dec!(100), None, None), None);
+    }
+
+    #[test]
+    pub fn mid_at_trade_time() {
+        let at = |secs: i64| Utc.timestamp_opt(1_700_000_000 + secs, 0).unwrap();
+        let mut mids = Mids::default();
+        assert_eq!(mids.at(at(0)), None);
+        mids.update(at(10), Some(dec!(100)));
+        mids.update(at(20), Some(dec!(102)));
+        // An unchanged mid keeps the time it took effect
+        mids.update(at(25), Some(dec!(102)));
+        assert_eq!(mids.at(at(30)), Some(dec!(102)));
+        assert_eq!(mids.at(at(20)), Some(dec!(102)));
+        assert_eq!(mids.at(at(15)), Some(dec!(100)));
+        assert_eq!(mids.at(at(5)), None);
+        // A one sided book has no mid
+        mids.update(at(40), None);
+        assert_eq!(mids.at(at(45)), None);
+        assert_eq!(mids.at(at(35)), Some(dec!(102)));
+    }
+}
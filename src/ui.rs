@@ -1,30 +1,31 @@
-use agg_ws::{client::Exchange, trades::Trade};
-use chrono::Utc;
+use std::time::Instant;
+
+use agg_ws::client::Exchange;
 use ratatui::{
     backend::Backend,
-    layout::{Alignment, Constraint, Direction, Layout},
+    layout::{Alignment, Constraint, Direction, Layout, Rect},
     style::{Color, Modifier, Style},
     text::{Line, Span, Text},
-    widgets::{Block, Borders, List, ListItem, Padding, Paragraph, Tabs},
+    widgets::{Block, Borders, Clear, List, ListItem, Padding, Paragraph, Tabs},
     Frame,
 };
 use rust_decimal::prelude::*;
 
-use crate::app::App;
-
-fn px_fmt(s: &str) -> String {
-    // Format the string of trade and price for the display by rounding to 5 significant figure
-    Decimal::from_str(s)
-        .unwrap()
-        .round_sf(7)
-        .unwrap()
-        .to_string()
-}
+use crate::{
+    app::{App, AppFocus, PromptKind},
+    bbo::best,
+    engine::Snapshot,
+    replay::{self, ReplayStatus},
+    subscriptions::{channel_type_display, SubState},
+    sweep::Fill,
+    symbols::{Instrument, VenueType},
+    tape::Side,
+    AggExchange, Level,
+};
 
-fn sz_fmt(s: &str, dp: u32) -> String {
-    let mut dec = Decimal::from_str(s).unwrap();
-    dec.rescale(dp);
-    format!("{}", dec)
+fn px_fmt(p: Decimal) -> String {
+    // Format the trade price for the display by rounding to 7 significant figures
+    p.round_sf(7).unwrap_or(p).to_string()
 }
 
 fn sz_fmt_dec(mut s: Decimal, dp: u32) -> String {
@@ -32,19 +33,176 @@ fn sz_fmt_dec(mut s: Decimal, dp: u32) -> String {
     format!("{}", s)
 }
 
+fn venue_abbrev(exchange: Exchange) -> &'static str {
+    match exchange {
+        Exchange::Kraken => "K",
+        Exchange::Gdax => "C",
+        Exchange::Hyperliquid => "H",
+    }
+}
+
+// Widths of the exchange list, tape and book panes. The tape and book columns are fixed so that
+// values are never clipped, panes that do not fit are moved or left out instead.
+const EXCHANGES_WIDTH: u16 = 17;
+const TAPE_WIDTH: u16 = 43;
+const BOOK_WIDTH: u16 = 68;
+
+// Stale venues are greyed out wherever they are listed
+const STALE_COLOR: Color = Color::DarkGray;
+
+fn venue_color(exchange: Exchange) -> Color {
+    match exchange {
+        Exchange::Kraken => Color::Magenta,
+        Exchange::Gdax => Color::Blue,
+        Exchange::Hyperliquid => Color::Green,
+    }
+}
+
+// Venue quoting a level, or each venue's share of the size when several quote the same price,
+// e.g. "K62 C38", with stale venues greyed out
+fn venue_breakdown(level: &Level, style: Style, stale: &[Exchange]) -> Line<'static> {
+    if let [(exchange, _)] = level.venues.as_slice() {
+        let style = if stale.contains(exchange) {
+            style.fg(STALE_COLOR)
+        } else {
+            style
+        };
+        return Line::from(Span::styled(level.exchange().as_display(), style));
+    }
+    let spans: Vec<Span> = level
+        .venues
+        .iter()
+        .enumerate()
+        .map(|(i, (exchange, size))| {
+            let share = size
+                .checked_div(level.size)
+                .unwrap_or_default()
+                .saturating_mul(Decimal::ONE_HUNDRED)
+                .round();
+            Span::styled(
+                format!(
+                    "{}{}{}",
+                    if i > 0 { " " } else { "" },
+                    venue_abbrev(*exchange),
+                    share
+                ),
+                style.fg(if stale.contains(exchange) {
+                    STALE_COLOR
+                } else {
+                    venue_color(*exchange)
+                }),
+            )
+        })
+        .collect();
+    Line::from(spans)
+}
+
+// Buys in green and sells in red, unclassified trades keep the row style
+fn side_style(side: Option<Side>, style: Style) -> Style {
+    match side {
+        Some(Side::Buy) => style.fg(Color::Green),
+        Some(Side::Sell) => style.fg(Color::Red),
+        None => style,
+    }
+}
+
+// Padding above and below the contents of a pane, dropped on short terminals
+fn pane_padding(compact: bool) -> Padding {
+    Padding::vertical(if compact { 0 } else { 1 })
+}
+
+// Area of a pane's columns, below its title and padding
+fn pane_body(area: Rect, compact: bool) -> Rect {
+    let (top, bottom) = if compact { (1, 0) } else { (2, 2) };
+    Rect {
+        y: area.y + top.min(area.height),
+        height: area.height.saturating_sub(top + bottom),
+        ..area
+    }
+}
+
+// A column's header, followed by a blank line unless the layout is compact
+fn column_header(
+    title: &'static str,
+    style: Style,
+    rows: usize,
+    compact: bool,
+) -> Vec<Line<'static>> {
+    let mut lines = Vec::with_capacity(rows + 2);
+    lines.push(Line::from(Span::styled(title, style)).alignment(Alignment::Right));
+    if !compact {
+        lines.push(Line::from("").alignment(Alignment::Right));
+    }
+    lines
+}
+
+// Pane title with an optional detail such as the book grouping, and how far it is scrolled
+fn pane_title(name: &str, detail: Option<&str>, scroll: usize) -> String {
+    let mut title = format!(" {} ", name);
+    if let Some(detail) = detail {
+        title.push_str(&format!("({}) ", detail));
+    }
+    if scroll > 0 {
+        title.push_str(&format!("+{} ", scroll));
+    }
+    title
+}
+
+fn pane_style(app: &App, pane: AppFocus, style: Style) -> Style {
+    if *app.focus() == pane {
+        style.fg(Color::White)
+    } else {
+        style
+    }
+}
+
 pub fn ui<B: Backend>(f: &mut Frame<B>, app: &mut App) {
     let size = f.size();
+    let asset = app.asset().to_string();
+    let dp = *app.dp.get(&asset).unwrap();
+    // The engine may not have caught up with a newly selected asset yet
+    let empty = Snapshot::default();
+    let snapshot = if app.snapshot.asset == asset {
+        &*app.snapshot
+    } else {
+        &empty
+    };
 
     // Render Full Screen Block
     let block = Block::default().style(Style::default().bg(Color::Black).fg(Color::Black));
     f.render_widget(block, size);
 
+    // Margins are only kept on terminals with room to spare, and short terminals also drop the
+    // padding inside panes
+    let h_margin = if size.width >= 160 { 5 } else { 1 };
+    let main_margin = if size.width >= 160 { 5 } else { 2 };
+    let v_margin = match size.height {
+        40.. => 2,
+        30.. => 1,
+        _ => 0,
+    };
+    let compact = size.height < 30;
+    let header_rows = if compact { 1 } else { 2 };
+
     // Split Screen into Top and Bottom Chunks
     let chunks = Layout::default()
         .direction(Direction::Vertical)
-        .horizontal_margin(5)
-        .vertical_margin(2)
-        .constraints([Constraint::Length(3), Constraint::Min(0)].as_ref())
+        .horizontal_margin(h_margin)
+        .vertical_margin(v_margin)
+        .constraints(
+            [
+                Constraint::Length(3),
+                // BBO header and one row per venue inside a border, up to a quarter of the
+                // screen, plus a row per crossed market so the banners are never cut off
+                Constraint::Length(
+                    (snapshot.bbo.len() as u16 + 3).min((size.height / 4).max(4))
+                        + (snapshot.crosses.len() as u16).min(size.height / 4),
+                ),
+                Constraint::Min(0),
+                Constraint::Length(1),
+            ]
+            .as_ref(),
+        )
         .split(size);
 
     // Render Tabs into Top Chunk
@@ -69,6 +227,9 @@ pub fn ui<B: Backend>(f: &mut Frame<B>, app: &mut App) {
         );
     f.render_widget(tabs, chunks[0]);
 
+    // Render Venue Best Bid and Offer below the Tabs
+    render_bbo(f, snapshot, chunks[1]);
+
     // Render Main Screen into Lower Chunk
     let exchange_block_style = Style::default()
         .fg(Color::LightYellow)
@@ -76,27 +237,19 @@ pub fn ui<B: Backend>(f: &mut Frame<B>, app: &mut App) {
         .add_modifier(Modifier::BOLD);
 
     let block = Block::default()
-        .title(format!(" {} ", app.assets[app.asset_idx]))
+        .title(format!(" {} ", app.instruments.get(&asset).unwrap()))
         .borders(Borders::ALL)
         .style(exchange_block_style);
-    f.render_widget(block, chunks[1]);
+    f.render_widget(block, chunks[2]);
 
-    // Split Main Screen into 3 Chunks
-    let main_chunks = Layout::default()
-        .direction(Direction::Horizontal)
-        .horizontal_margin(5)
-        .vertical_margin(2)
-        .constraints(
-            [
-                Constraint::Length(15),
-                Constraint::Percentage(2),
-                Constraint::Length(50),
-                Constraint::Percentage(2),
-                Constraint::Length(50),
-            ]
-            .as_ref(),
-        )
-        .split(chunks[1]);
+    // Split Main Screen into the Exchanges, Trades and Book Chunks
+    let main = Layout::default()
+        .horizontal_margin(main_margin)
+        .vertical_margin(v_margin.max(1))
+        .constraints([Constraint::Min(0)].as_ref())
+        .split(chunks[2])[0];
+    let n_exchanges = app.exchanges.get(&asset).map_or(0, |e| e.len()) as u16;
+    let (exchanges_area, trades_area, book_area) = main_areas(main, n_exchanges);
 
     // Set Styles
     let header_style = Style::default()
@@ -107,13 +260,34 @@ pub fn ui<B: Backend>(f: &mut Frame<B>, app: &mut App) {
         .fg(Color::White)
         .add_modifier(Modifier::BOLD);
 
-    // Render Exchanges List into Left Main Chunk
+    // Render Exchanges List into Left Main Chunk, stale venues greyed out and struck through once
+    // they are dropped from the aggregated book
     let exchanges: Vec<ListItem> = app
         .exchanges
-        .get(&app.asset_idx)
+        .get(&asset)
         .unwrap()
         .iter()
-        .map(|e| ListItem::new(Text::from(e.as_display())))
+        .map(|e| match e {
+            AggExchange::Aggregate => ListItem::new(Text::from(e.as_display())),
+            AggExchange::Exchange(ex) => {
+                let item = ListItem::new(Text::from(format!(
+                    "{} {}",
+                    e.as_display(),
+                    VenueType::of(*ex).as_display()
+                )));
+                if snapshot.excluded.contains(ex) {
+                    item.style(
+                        Style::default()
+                            .fg(STALE_COLOR)
+                            .add_modifier(Modifier::CROSSED_OUT),
+                    )
+                } else if snapshot.stale.contains(ex) {
+                    item.style(Style::default().fg(STALE_COLOR))
+                } else {
+                    item
+                }
+            }
+        })
         .collect();
     let exchanges = List::new(exchanges)
         .block(
@@ -122,7 +296,7 @@ pub fn ui<B: Backend>(f: &mut Frame<B>, app: &mut App) {
                 .title(" Exchanges ")
                 .title_alignment(Alignment::Center)
                 .style(exchange_block_style)
-                .padding(Padding::vertical(1)),
+                .padding(pane_padding(compact)),
         )
         .highlight_style(
             Style::default()
@@ -131,260 +305,82 @@ pub fn ui<B: Backend>(f: &mut Frame<B>, app: &mut App) {
                 .fg(Color::LightYellow),
         )
         .style(Style::default().fg(Color::White));
-    let exchange_state = app.exchange_state.get_mut(&app.asset_idx).unwrap();
-    f.render_stateful_widget(exchanges, main_chunks[0], exchange_state);
+    let exchange_state = app.exchange_state.get_mut(&asset).unwrap();
+    f.render_stateful_widget(exchanges, exchanges_area, exchange_state);
 
     // Render Trades into Middle Main Chunk
     // Split the Main Chunk into 4 Vertical Chunks - Size | Price | Time | Exchange
     // Split each Vertical Chunk into 51 Horizontal Chunks - One for each trade and a single header
     // Table Widget cannot be used as Alignment is not Available
 
-    // Render Trades Box into Center Main Chunk
-    let block = Block::default()
-        .title(" Trades ")
-        .borders(Borders::TOP)
-        .title_alignment(Alignment::Center)
-        .padding(Padding::vertical(1))
-        .style(exchange_block_style);
-    f.render_widget(block, main_chunks[2]);
-
     let trade_columns = Layout::default()
         .direction(Direction::Horizontal)
-        .margin(2)
         .constraints(
             [
-                Constraint::Max(1),
+                Constraint::Length(1),
                 Constraint::Length(10), // Size
-                Constraint::Length(2),  // Space
+                Constraint::Length(1),  // Space
                 Constraint::Length(10), // Price
-                Constraint::Length(2),  // Space
+                Constraint::Length(1),  // Space
                 Constraint::Length(8),  // Time
-                Constraint::Length(2),  // Space
-                Constraint::Length(13), // Exchange
-                Constraint::Percentage(1),
+                Constraint::Length(1),  // Space
+                Constraint::Length(11), // Exchange
+                Constraint::Min(0),
             ]
             .as_ref(),
         )
-        .split(main_chunks[2]);
-
-    let _test_trades = vec![
-        Trade {
-            size: "123.23".to_string(),
-            price: "123214.213".to_string(),
-            dt: Utc::now(),
-            exchange: Exchange::Kraken,
-        },
-        Trade {
-            size: "23.23".to_string(),
-            price: "123214.213".to_string(),
-            dt: Utc::now(),
-            exchange: Exchange::Kraken,
-        },
-        Trade {
-            size: "23.23".to_string(),
-            price: "123214.213".to_string(),
-            dt: Utc::now(),
-            exchange: Exchange::Kraken,
-        },
-        Trade {
-            size: "133.23".to_string(),
-            price: "123214.213".to_string(),
-            dt: Utc::now(),
-            exchange: Exchange::Kraken,
-        },
-        Trade {
-            size: "143.23".to_string(),
-            price: "123214.213".to_string(),
-            dt: Utc::now(),
-            exchange: Exchange::Kraken,
-        },
-        Trade {
-            size: "153.23".to_string(),
-            price: "123215.213".to_string(),
-            dt: Utc::now(),
-            exchange: Exchange::Kraken,
-        },
-        Trade {
-            size: "143.23".to_string(),
-            price: "123214.213".to_string(),
-            dt: Utc::now(),
-            exchange: Exchange::Kraken,
-        },
-        Trade {
-            size: "133.23".to_string(),
-            price: "123214.213".to_string(),
-            dt: Utc::now(),
-            exchange: Exchange::Kraken,
-        },
-        Trade {
-            size: "3.23".to_string(),
-            price: "123214.613".to_string(),
-            dt: Utc::now(),
-            exchange: Exchange::Kraken,
-        },
-        Trade {
-            size: "1253.23".to_string(),
-            price: "123213.213".to_string(),
-            dt: Utc::now(),
-            exchange: Exchange::Kraken,
-        },
-        Trade {
-            size: "12343.23".to_string(),
-            price: "123212.213".to_string(),
-            dt: Utc::now(),
-            exchange: Exchange::Kraken,
-        },
-        Trade {
-            size: "12.23".to_string(),
-            price: "123216.213".to_string(),
-            dt: Utc::now(),
-            exchange: Exchange::Kraken,
-        },
-        Trade {
-            size: "13.23".to_string(),
-            price: "123218.213".to_string(),
-            dt: Utc::now(),
-            exchange: Exchange::Kraken,
-        },
-        Trade {
-            size: "3.23".to_string(),
-            price: "123218.213".to_string(),
-            dt: Utc::now(),
-            exchange: Exchange::Kraken,
-        },
-        Trade {
-            size: "13.23".to_string(),
-            price: "123217.213".to_string(),
-            dt: Utc::now(),
-            exchange: Exchange::Kraken,
-        },
-        Trade {
-            size: "133.23".to_string(),
-            price: "123216.213".to_string(),
-            dt: Utc::now(),
-            exchange: Exchange::Kraken,
-        },
-        Trade {
-            size: "143.23".to_string(),
-            price: "123215.213".to_string(),
-            dt: Utc::now(),
-            exchange: Exchange::Kraken,
-        },
-        Trade {
-            size: "133.23".to_string(),
-            price: "123215.213".to_string(),
-            dt: Utc::now(),
-            exchange: Exchange::Kraken,
-        },
-        Trade {
-            size: "143.23".to_string(),
-            price: "123214.213".to_string(),
-            dt: Utc::now(),
-            exchange: Exchange::Kraken,
-        },
-        Trade {
-            size: "133.23".to_string(),
-            price: "123214.213".to_string(),
-            dt: Utc::now(),
-            exchange: Exchange::Kraken,
-        },
-        Trade {
-            size: "143.23".to_string(),
-            price: "123234.213".to_string(),
-            dt: Utc::now(),
-            exchange: Exchange::Kraken,
-        },
-        Trade {
-            size: "23.23".to_string(),
-            price: "123256.213".to_string(),
-            dt: Utc::now(),
-            exchange: Exchange::Kraken,
-        },
-        Trade {
-            size: "23.23".to_string(),
-            price: "123245.213".to_string(),
-            dt: Utc::now(),
-            exchange: Exchange::Kraken,
-        },
-        Trade {
-            size: "23.23".to_string(),
-            price: "123243.213".to_string(),
-            dt: Utc::now(),
-            exchange: Exchange::Kraken,
-        },
-        Trade {
-            size: "3.23".to_string(),
-            price: "123214.213".to_string(),
-            dt: Utc::now(),
-            exchange: Exchange::Kraken,
-        },
-        Trade {
-            size: "23.23".to_string(),
-            price: "123344.213".to_string(),
-            dt: Utc::now(),
-            exchange: Exchange::Kraken,
-        },
-        Trade {
-            size: "0.23".to_string(),
-            price: "123244.213".to_string(),
-            dt: Utc::now(),
-            exchange: Exchange::Kraken,
-        },
-        Trade {
-            size: "13.23".to_string(),
-            price: "123414.213".to_string(),
-            dt: Utc::now(),
-            exchange: Exchange::Kraken,
-        },
-        Trade {
-            size: "13.23".to_string(),
-            price: "123314.213".to_string(),
-            dt: Utc::now(),
-            exchange: Exchange::Kraken,
-        },
-        Trade {
-            size: "3.23".to_string(),
-            price: "123254.213".to_string(),
-            dt: Utc::now(),
-            exchange: Exchange::Kraken,
-        },
-    ];
-    // app.trades = test_trades;
-    let n = app.trades.len();
-    let (sizes, prices, dts, exchanges) = app.trades.iter().fold(
-        {
-            let mut sizes = Vec::with_capacity(n + 2);
-            sizes.push(Line::from(Span::styled("Size ", header_style)).alignment(Alignment::Right));
-            sizes.push(Line::from("").alignment(Alignment::Right));
-            let mut prices = Vec::with_capacity(n + 2);
-            prices.push(Line::from("Price ").alignment(Alignment::Right));
-            prices.push(Line::from("").alignment(Alignment::Right));
-            let mut dts = Vec::with_capacity(n + 2);
-            dts.push(Line::from("Time ").alignment(Alignment::Right));
-            dts.push(Line::from("").alignment(Alignment::Right));
-            let mut exchanges = Vec::with_capacity(n + 2);
-            exchanges.push(Line::from("Exchange ").alignment(Alignment::Right));
-            exchanges.push(Line::from("").alignment(Alignment::Right));
-            (sizes, prices, dts, exchanges)
-        },
+        .split(pane_body(trades_area, compact));
+
+    // Rows below the header, scrolled no further than the last trade
+    app.tape_rows = (trade_columns[1].height as usize).saturating_sub(header_rows);
+    app.tape_scroll = app
+        .tape_scroll
+        .min(snapshot.trades.len().saturating_sub(app.tape_rows));
+
+    // Render Trades Box into Center Main Chunk
+    let block = Block::default()
+        .title(pane_title("Trades", None, app.tape_scroll))
+        .borders(Borders::TOP)
+        .title_alignment(Alignment::Center)
+        .padding(pane_padding(compact))
+        .style(pane_style(app, AppFocus::Trades, exchange_block_style));
+    f.render_widget(block, trades_area);
+
+    let n = app.tape_rows;
+    let trades = snapshot
+        .trades
+        .iter()
+        .skip(app.tape_scroll)
+        .take(app.tape_rows);
+    let (sizes, prices, dts, exchanges) = trades.fold(
+        (
+            column_header("Size", header_style, n, compact),
+            column_header("Price", header_style, n, compact),
+            column_header("Time", header_style, n, compact),
+            column_header("Exchange", header_style, n, compact),
+        ),
         |(mut s, mut p, mut d, mut e), t| {
             s.push(
                 Line::from(Span::styled(
-                    sz_fmt(&t.size, *app.dp.get(&app.asset_idx).unwrap()),
-                    row_style,
+                    sz_fmt_dec(t.size, dp),
+                    side_style(t.side, row_style),
                 ))
                 .alignment(Alignment::Right),
             );
             p.push(
-                Line::from(Span::styled(px_fmt(&t.price), row_style)).alignment(Alignment::Right),
+                Line::from(Span::styled(px_fmt(t.price), side_style(t.side, row_style)))
+                    .alignment(Alignment::Right),
             );
             d.push(
                 Line::from(Span::styled(format!("{}", t.dt.time()), row_style))
                     .alignment(Alignment::Right),
             );
             e.push(
-                Line::from(Span::styled(t.exchange.as_display(), row_style))
-                    .alignment(Alignment::Right),
+                Line::from(Span::styled(
+                    AggExchange::Exchange(t.exchange).as_display(),
+                    row_style,
+                ))
+                .alignment(Alignment::Right),
             );
             (s, p, d, e)
         },
@@ -403,64 +399,63 @@ pub fn ui<B: Backend>(f: &mut Frame<B>, app: &mut App) {
 
     // Render Book Box into Left Main Chunk
     // Split the Main Chunk into 6 Vertical Chunks - BidExchange | BidSize | BidPrice | AskPrice | AskSize | AskExchange
-    let block = Block::default()
-        .title(" Book ")
-        .borders(Borders::TOP)
-        .title_alignment(Alignment::Center)
-        .padding(Padding::vertical(1))
-        .style(exchange_block_style);
-    f.render_widget(block, main_chunks[4]);
-
     let book_columns = Layout::default()
         .direction(Direction::Horizontal)
-        .margin(2)
         .constraints(
             [
-                Constraint::Max(1),
-                Constraint::Length(13), // Exchange
-                Constraint::Length(2),  // Space
+                Constraint::Length(1),
+                Constraint::Length(11), // Exchange
+                Constraint::Length(1),  // Space
                 Constraint::Length(10), // Size
-                Constraint::Length(2),  // Space
+                Constraint::Length(1),  // Space
                 Constraint::Length(10), // Price
-                Constraint::Length(2),  // Space
+                Constraint::Length(1),  // Space
                 Constraint::Length(10), // Price
-                Constraint::Length(2),  // Space
+                Constraint::Length(1),  // Space
                 Constraint::Length(10), // Size
-                Constraint::Length(2),  // Space
-                Constraint::Length(13), // Exchange
-                Constraint::Percentage(1),
+                Constraint::Length(1),  // Space
+                Constraint::Length(11), // Exchange
+                Constraint::Min(0),
             ]
             .as_ref(),
         )
-        .split(main_chunks[4]);
+        .split(pane_body(book_area, compact));
+
+    // Unscrolled, the first row is always the inside spread
+    app.book_rows = (book_columns[3].height as usize).saturating_sub(header_rows);
+    let n_levels = snapshot.book.bids.len().max(snapshot.book.asks.len());
+    app.book_scroll = app.book_scroll.min(n_levels.saturating_sub(app.book_rows));
+
+    let grouping = app.grouping().map(|g| g.to_string());
+    let block = Block::default()
+        .title(pane_title("Book", grouping.as_deref(), app.book_scroll))
+        .borders(Borders::TOP)
+        .title_alignment(Alignment::Center)
+        .padding(pane_padding(compact))
+        .style(pane_style(app, AppFocus::Book, exchange_block_style));
+    f.render_widget(block, book_area);
 
     // Set Styles
     let bid_row_style = Style::default().fg(Color::Cyan);
 
-    let n_bid = app.book.bids.len();
-    let (prices, sizes, exchanges) = app.book.bids.iter().rev().fold(
-        {
-            let mut sizes = Vec::with_capacity(n_bid + 2);
-            sizes.push(Line::from(Span::styled("Size", header_style)).alignment(Alignment::Right));
-            sizes.push(Line::from(Span::styled("", header_style)).alignment(Alignment::Right));
-            let mut prices = Vec::with_capacity(n + 2);
-            prices
-                .push(Line::from(Span::styled("Price", header_style)).alignment(Alignment::Right));
-            prices.push(Line::from("").alignment(Alignment::Right));
-            let mut exchanges = Vec::with_capacity(n + 2);
-            exchanges.push(
-                Line::from(Span::styled("Exchange", header_style)).alignment(Alignment::Right),
-            );
-            exchanges.push(Line::from("").alignment(Alignment::Right));
-            (prices, sizes, exchanges)
-        },
+    let n_bid = app.book_rows;
+    let bids = snapshot
+        .book
+        .bids
+        .iter()
+        .rev()
+        .skip(app.book_scroll)
+        .take(app.book_rows);
+    let (prices, sizes, exchanges) = bids.fold(
+        (
+            column_header("Price", header_style, n_bid, compact),
+            column_header("Size", header_style, n_bid, compact),
+            column_header("Exchange", header_style, n_bid, compact),
+        ),
         |(mut p, mut s, mut e), l| {
             s.push(
-                Line::from(Span::styled(
-                    sz_fmt_dec(l.1.size, *app.dp.get(&app.asset_idx).unwrap()),
-                    row_style,
-                ))
-                .alignment(Alignment::Right),
+                Line::from(Span::styled(sz_fmt_dec(l.1.size, dp), row_style))
+                    .alignment(Alignment::Right),
             );
             p.push(
                 Line::from(Span::styled(
@@ -469,10 +464,7 @@ pub fn ui<B: Backend>(f: &mut Frame<B>, app: &mut App) {
                 ))
                 .alignment(Alignment::Right),
             );
-            e.push(
-                Line::from(Span::styled(l.1.exchange.as_display(), row_style))
-                    .alignment(Alignment::Right),
-            );
+            e.push(venue_breakdown(l.1, row_style, &snapshot.stale).alignment(Alignment::Right));
             (p, s, e)
         },
     );
@@ -488,27 +480,23 @@ pub fn ui<B: Backend>(f: &mut Frame<B>, app: &mut App) {
 
     let ask_row_style = Style::default().fg(Color::Red);
 
-    let n_ask = app.book.asks.len();
-    let (prices, sizes, exchanges) = app.book.asks.iter().fold(
-        {
-            let mut sizes = Vec::with_capacity(n_ask + 2);
-            sizes.push(Line::from(Span::styled("Size ", header_style)).alignment(Alignment::Right));
-            sizes.push(Line::from("").alignment(Alignment::Right));
-            let mut prices = Vec::with_capacity(n_ask + 2);
-            prices.push(Line::from("Price ").alignment(Alignment::Right));
-            prices.push(Line::from("").alignment(Alignment::Right));
-            let mut exchanges = Vec::with_capacity(n_ask + 2);
-            exchanges.push(Line::from("Exchange ").alignment(Alignment::Right));
-            exchanges.push(Line::from("").alignment(Alignment::Right));
-            (prices, sizes, exchanges)
-        },
+    let n_ask = app.book_rows;
+    let asks = snapshot
+        .book
+        .asks
+        .iter()
+        .skip(app.book_scroll)
+        .take(app.book_rows);
+    let (prices, sizes, exchanges) = asks.fold(
+        (
+            column_header("Price", header_style, n_ask, compact),
+            column_header("Size", header_style, n_ask, compact),
+            column_header("Exchange", header_style, n_ask, compact),
+        ),
         |(mut p, mut s, mut e), l| {
             s.push(
-                Line::from(Span::styled(
-                    sz_fmt_dec(l.1.size, *app.dp.get(&app.asset_idx).unwrap()),
-                    row_style,
-                ))
-                .alignment(Alignment::Right),
+                Line::from(Span::styled(sz_fmt_dec(l.1.size, dp), row_style))
+                    .alignment(Alignment::Right),
             );
             p.push(
                 Line::from(Span::styled(
@@ -517,10 +505,7 @@ pub fn ui<B: Backend>(f: &mut Frame<B>, app: &mut App) {
                 ))
                 .alignment(Alignment::Right),
             );
-            e.push(
-                Line::from(Span::styled(l.1.exchange.as_display(), row_style))
-                    .alignment(Alignment::Right),
-            );
+            e.push(venue_breakdown(l.1, row_style, &snapshot.stale).alignment(Alignment::Right));
             (p, s, e)
         },
     );
@@ -533,11 +518,737 @@ pub fn ui<B: Backend>(f: &mut Frame<B>, app: &mut App) {
 
     let exchange_paragraph = Paragraph::new(exchanges.clone()).alignment(Alignment::Right);
     f.render_widget(exchange_paragraph, book_columns[11]);
+
+    // Render Subscription Status or Replay Timeline Line into Bottom Chunk
+    let status_line = match &snapshot.replay {
+        Some(status) => replay_timeline(status, chunks[3].width),
+        None => subscription_status(app),
+    };
+    f.render_widget(Paragraph::new(status_line), chunks[3]);
+
+    // Render Subscriptions Panel over the Main Screen
+    if app.show_subs {
+        render_subscriptions(f, app, chunks[2]);
+    }
+
+    // Render Cost to Fill Panel along the bottom of the Main Screen
+    if let Some(report) = &snapshot.sweep {
+        let instrument = app.instruments.get(&asset).unwrap();
+        let height = (report.venues.len() as u16 + 5).min(chunks[2].height);
+        let area = Rect {
+            x: chunks[2].x + 2,
+            y: chunks[2].y + chunks[2].height - height,
+            width: chunks[2].width.saturating_sub(4),
+            height,
+        };
+        render_sweep(f, snapshot, instrument, dp, area);
+    }
+
+    // Render Add Asset or Cost to Fill Prompt over the Main Screen
+    if let Some(prompt) = &app.prompt {
+        let area = Rect {
+            x: chunks[2].x + chunks[2].width / 4,
+            y: chunks[2].y + chunks[2].height / 3,
+            width: chunks[2].width / 2,
+            height: 5.min(chunks[2].height),
+        };
+        let instrument = app.instruments.get(&asset).unwrap();
+        let (title, usage) = match prompt.kind {
+            PromptKind::AddAsset => (
+                " Add Asset ",
+                "BASE[/QUOTE] [size_dp] exchange[=symbol] ...  (Enter to add, Esc to cancel)"
+                    .to_string(),
+            ),
+            PromptKind::Sweep => (
+                " Cost to Fill ",
+                format!(
+                    "buy|sell SIZE [{}|{}]  (Enter to price, Esc to cancel)",
+                    instrument.base, instrument.quote
+                ),
+            ),
+            PromptKind::Jump => (
+                " Jump To ",
+                "HH:MM[:SS] UTC or +30s, -5m, +1h  (Enter to jump, Esc to cancel)".to_string(),
+            ),
+        };
+        let (status, status_style) = match &prompt.error {
+            Some(e) => (e.clone(), Style::default().fg(Color::Red)),
+            None => (usage, Style::default().fg(Color::DarkGray)),
+        };
+        let text = vec![
+            Line::from(Span::styled(format!("> {}", prompt.input), row_style)),
+            Line::from(Span::styled(status, status_style)),
+        ];
+        let paragraph = Paragraph::new(text).block(
+            Block::default()
+                .title(title)
+                .borders(Borders::ALL)
+                .padding(Padding::horizontal(1))
+                .style(exchange_block_style),
+        );
+        f.render_widget(Clear, area);
+        f.render_widget(paragraph, area);
+    }
+}
+
+// Areas of the exchange list, tape and book within the main screen. The three sit side by side
+// when they fit, otherwise the exchange list moves above the tape. On narrow terminals the book
+// keeps its full width with the exchange list and tape below it.
+fn main_areas(area: Rect, n_exchanges: u16) -> (Rect, Rect, Rect) {
+    let side_by_side = EXCHANGES_WIDTH + TAPE_WIDTH + BOOK_WIDTH + 2;
+    if area.width >= side_by_side {
+        // Up to three columns between panes, with anything left over on the right
+        let gap = ((area.width - side_by_side) / 2).min(3) + 1;
+        let chunks = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints(
+                [
+                    Constraint::Length(EXCHANGES_WIDTH),
+                    Constraint::Length(gap),
+                    Constraint::Length(TAPE_WIDTH),
+                    Constraint::Length(gap),
+                    Constraint::Length(BOOK_WIDTH),
+                    Constraint::Min(0),
+                ]
+                .as_ref(),
+            )
+            .split(area);
+        return (chunks[0], chunks[2], chunks[4]);
+    }
+    let left = area.width.saturating_sub(BOOK_WIDTH + 1);
+    if left < TAPE_WIDTH {
+        let rows = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Min(0), Constraint::Length(area.height / 2)].as_ref())
+            .split(area);
+        let below = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints(
+                [
+                    Constraint::Length(EXCHANGES_WIDTH),
+                    Constraint::Length(1),
+                    Constraint::Length(TAPE_WIDTH),
+                    Constraint::Min(0),
+                ]
+                .as_ref(),
+            )
+            .split(rows[1]);
+        return (below[0], below[2], rows[0]);
+    }
+    let chunks = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints(
+            [
+                Constraint::Length(left),
+                Constraint::Length(1),
+                Constraint::Length(BOOK_WIDTH),
+                Constraint::Min(0),
+            ]
+            .as_ref(),
+        )
+        .split(area);
+    // Border, padding above and below and one row per exchange
+    let stacked = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Length(n_exchanges + 3), Constraint::Min(0)].as_ref())
+        .split(chunks[0]);
+    (stacked[0], stacked[1], chunks[2])
+}
+
+// Playback state, position and a progress bar filling the rest of the line
+fn replay_timeline(status: &ReplayStatus, width: u16) -> Line<'static> {
+    let text_style = Style::default().fg(Color::DarkGray);
+    let time = |micros| {
+        replay::to_datetime(micros)
+            .map(|t| t.format("%H:%M:%S").to_string())
+            .unwrap_or_default()
+    };
+    let state = if status.paused { " ⏸ " } else { " ▶ " };
+    let head = format!("{}x {} ", status.speed, time(status.position));
+    let tail = format!(" {}  ", time(status.end));
+    let help = "(space: pause, +/-: speed, n: step, j: jump)";
+    let used = state.chars().count() + head.len() + tail.len() + help.len() + 2;
+    let bar = (width as usize).saturating_sub(used).max(10);
+    let span = (status.end - status.start).max(1) as f64;
+    let done = ((status.position - status.start) as f64 / span * bar as f64) as usize;
+    let done = done.min(bar);
+    Line::from(vec![
+        Span::styled(
+            state,
+            Style::default().fg(if status.paused {
+                Color::LightYellow
+            } else {
+                Color::Green
+            }),
+        ),
+        Span::styled(head, Style::default().fg(Color::White)),
+        Span::styled("[", text_style),
+        Span::styled("=".repeat(done), Style::default().fg(Color::LightBlue)),
+        Span::styled("-".repeat(bar - done), text_style),
+        Span::styled("]", text_style),
+        Span::styled(tail, Style::default().fg(Color::White)),
+        Span::styled(help, text_style),
+    ])
+}
+
+fn subscription_status(app: &App) -> Line<'static> {
+    let text_style = Style::default().fg(Color::DarkGray);
+    let mut spans = vec![Span::styled(" Subscriptions: ", text_style)];
+    for (state, color) in [
+        (SubState::Subscribed, Color::Green),
+        (SubState::Queued, Color::White),
+        (SubState::Requesting, Color::LightYellow),
+        (SubState::Failed, Color::Red),
+        (SubState::Unsubscribing, Color::DarkGray),
+    ] {
+        let n = app
+            .snapshot
+            .subscriptions
+            .iter()
+            .filter(|(_, e)| e.state == state)
+            .count();
+        if n > 0 {
+            spans.push(Span::styled(
+                format!("{} {}  ", n, state.as_display().to_lowercase()),
+                Style::default().fg(color),
+            ));
+        }
+    }
+    if app.snapshot.dropped_trades > 0 {
+        spans.push(Span::styled(
+            format!("{} bad trades dropped  ", app.snapshot.dropped_trades),
+            Style::default().fg(Color::Red),
+        ));
+    }
+    spans.push(Span::styled("(s: details, r: retry)", text_style));
+    Line::from(spans)
+}
+
+fn render_bbo<B: Backend>(f: &mut Frame<B>, snapshot: &Snapshot, area: Rect) {
+    let (best_bid, best_ask) = best(&snapshot.bbo, &snapshot.stale);
+    let header_style = Style::default()
+        .fg(Color::LightYellow)
+        .add_modifier(Modifier::BOLD);
+    let row_style = Style::default().fg(Color::White);
+    // Venues setting the consolidated best bid or offer are highlighted
+    let best_style = |side_color: Color| {
+        Style::default()
+            .fg(Color::Black)
+            .bg(side_color)
+            .add_modifier(Modifier::BOLD)
+    };
+    let mut lines = vec![Line::from(Span::styled(
+        format!(
+            "{:<17}{:>10} {:>10}  {:<10}{:>10}{:>7}{:>7}",
+            "Exchange", "Bid Size", "Bid", "Ask", "Ask Size", "Bps", "Age"
+        ),
+        header_style,
+    ))];
+    // Venue rows give way to the crossed market banners when the panel is short
+    let venue_rows = (area.height as usize)
+        .saturating_sub(3)
+        .saturating_sub(snapshot.crosses.len());
+    for bbo in snapshot.bbo.iter().take(venue_rows) {
+        let exchange = AggExchange::Exchange(bbo.exchange);
+        // A stale venue's quotes are greyed out, however good they look
+        let stale = snapshot.stale.contains(&bbo.exchange);
+        let row_style = if stale {
+            row_style.fg(STALE_COLOR)
+        } else {
+            row_style
+        };
+        let (bid, bid_size) = match bbo.bid {
+            Some((p, s)) => (px_fmt(p), s.normalize().to_string()),
+            None => ("-".to_string(), String::new()),
+        };
+        let (ask, ask_size) = match bbo.ask {
+            Some((p, s)) => (px_fmt(p), s.normalize().to_string()),
+            None => ("-".to_string(), String::new()),
+        };
+        let bid_style = match (bbo.bid, best_bid) {
+            _ if stale => row_style,
+            (Some((p, _)), Some(b)) if p == b => best_style(Color::Cyan),
+            _ => Style::default().fg(Color::Cyan),
+        };
+        let ask_style = match (bbo.ask, best_ask) {
+            _ if stale => row_style,
+            (Some((p, _)), Some(a)) if p == a => best_style(Color::Red),
+            _ => Style::default().fg(Color::Red),
+        };
+        let spread = bbo
+            .spread_bps()
+            .map(|s| s.round_dp(1).to_string())
+            .unwrap_or_default();
+        let age = bbo
+            .age
+            .map(|age| format!("{:.1}s", age.as_secs_f32()))
+            .unwrap_or_default();
+        lines.push(Line::from(vec![
+            Span::styled(
+                format!(
+                    "{:<17}{:>10} ",
+                    format!(
+                        "{} {}",
+                        exchange.as_display(),
+                        VenueType::of(bbo.exchange).as_display()
+                    ),
+                    bid_size
+                ),
+                row_style,
+            ),
+            Span::styled(format!("{:>10}", bid), bid_style),
+            Span::raw("  "),
+            Span::styled(format!("{:<10}", ask), ask_style),
+            Span::styled(
+                format!("{:>10}{:>7}{:>7}", ask_size, spread, age),
+                row_style,
+            ),
+        ]));
+    }
+    // Banner for each pair of venues where one's bid is above the other's ask
+    let banner_style = Style::default()
+        .fg(Color::White)
+        .bg(Color::Red)
+        .add_modifier(Modifier::BOLD);
+    for (cross, duration) in snapshot.crosses.iter() {
+        lines.push(Line::from(Span::styled(
+            format!(
+                " CROSSED {:.1}s  {} ",
+                duration.as_secs_f32(),
+                cross.describe()
+            ),
+            banner_style,
+        )));
+    }
+    let block = Block::default()
+        .title(" Best Bid / Offer ")
+        .borders(Borders::ALL)
+        .padding(Padding::horizontal(1))
+        .style(header_style);
+    f.render_widget(Paragraph::new(lines).block(block), area);
+}
+
+// Cost of the order on the aggregated book and on each venue alone. Fills the book could not
+// complete are shown in red with the size they reached.
+fn render_sweep<B: Backend>(
+    f: &mut Frame<B>,
+    snapshot: &Snapshot,
+    instrument: &Instrument,
+    dp: u32,
+    area: Rect,
+) {
+    let Some(report) = &snapshot.sweep else {
+        return;
+    };
+    let header_style = Style::default()
+        .fg(Color::LightYellow)
+        .add_modifier(Modifier::BOLD);
+    let row_style = Style::default().fg(Color::White);
+    let row = |name: String, fill: &Fill| {
+        let filled_style = if fill.complete {
+            row_style
+        } else {
+            row_style.fg(Color::Red)
+        };
+        let slippage = report
+            .mid
+            .and_then(|mid| fill.slippage_bps(report.order.side, mid))
+            .map(|s| s.round_dp(1).to_string())
+            .unwrap_or_default();
+        let mut spans = vec![
+            Span::styled(format!("{:<18}", name), row_style),
+            Span::styled(format!("{:>14}", sz_fmt_dec(fill.filled, dp)), filled_style),
+            Span::styled(
+                format!(
+                    "{:>16}{:>14}{:>14}{:>10}  ",
+                    fill.notional.round_dp(2),
+                    fill.average().map(px_fmt).unwrap_or_default(),
+                    fill.worst.map(px_fmt).unwrap_or_default(),
+                    slippage
+                ),
+                row_style,
+            ),
+        ];
+        if fill.venues.len() > 1 {
+            let level = Level {
+                size: fill.filled,
+                venues: fill.venues.clone(),
+            };
+            spans.extend(venue_breakdown(&level, row_style, &snapshot.stale).spans);
+        }
+        Line::from(spans)
+    };
+    let mut lines = vec![Line::from(Span::styled(
+        format!(
+            "{:<18}{:>14}{:>16}{:>14}{:>14}{:>10}  {}",
+            "Venue", "Filled", "Cost", "Avg Price", "Worst", "Slip Bps", "Split"
+        ),
+        header_style,
+    ))];
+    lines.push(row("Aggregate".to_string(), &report.aggregate));
+    for (exchange, fill) in report.venues.iter() {
+        lines.push(row(
+            format!(
+                "{} {}",
+                AggExchange::Exchange(*exchange).as_display(),
+                VenueType::of(*exchange).as_display()
+            ),
+            fill,
+        ));
+    }
+    let mid = report
+        .mid
+        .map(|m| format!("mid {} ", px_fmt(m)))
+        .unwrap_or_default();
+    let block = Block::default()
+        .title(format!(
+            " Cost to Fill: {} {}",
+            report.order.describe(instrument),
+            mid
+        ))
+        .borders(Borders::ALL)
+        .padding(Padding::horizontal(1))
+        .style(header_style);
+    f.render_widget(Clear, area);
+    f.render_widget(Paragraph::new(lines).block(block), area);
+}
+
+fn render_subscriptions<B: Backend>(f: &mut Frame<B>, app: &App, area: Rect) {
+    let now = Instant::now();
+    let mut entries: Vec<_> = app.snapshot.subscriptions.iter().collect();
+    entries.sort_by_key(|(c, _)| {
+        (
+            AggExchange::Exchange(c.exchange).as_display(),
+            c.market.clone(),
+            channel_type_display(&c.channel),
+        )
+    });
+    let lines: Vec<Line> = entries
+        .into_iter()
+        .map(|(c, e)| {
+            let color = match e.state {
+                SubState::Subscribed => Color::Green,
+                SubState::Queued => Color::White,
+                SubState::Requesting => Color::LightYellow,
+                SubState::Failed => Color::Red,
+                SubState::Unsubscribing => Color::DarkGray,
+            };
+            let detail = match (e.state, e.retry_at, &e.error) {
+                (SubState::Failed, Some(t), Some(err)) => format!(
+                    "attempt {}, retry in {}s: {}",
+                    e.attempts,
+                    t.saturating_duration_since(now).as_secs(),
+                    err
+                ),
+                (SubState::Failed, None, Some(err)) => {
+                    format!("gave up after {} attempts: {}", e.attempts, err)
+                }
+                _ => String::new(),
+            };
+            Line::from(vec![
+                Span::styled(
+                    format!(
+                        "{:<12} {:<10} {:<5} ",
+                        AggExchange::Exchange(c.exchange).as_display(),
+                        c.market,
+                        channel_type_display(&c.channel)
+                    ),
+                    Style::default().fg(Color::White),
+                ),
+                Span::styled(
+                    format!("{:<14}", e.state.as_display()),
+                    Style::default().fg(color),
+                ),
+                Span::styled(detail, Style::default().fg(Color::DarkGray)),
+            ])
+        })
+        .collect();
+    let block = Block::default()
+        .title(" Subscriptions ")
+        .borders(Borders::ALL)
+        .padding(Padding::horizontal(1))
+        .style(
+            Style::default()
+                .fg(Color::LightYellow)
+                .add_modifier(Modifier::BOLD),
+        );
+    f.render_widget(Clear, area);
+    f.render_widget(Paragraph::new(lines).block(block), area);
 }
 
 #[cfg(test)]
 mod tests {
+    use std::{env, fs, path::PathBuf, sync::Arc, time::Duration};
+
+    use chrono::{TimeZone, Utc};
+    use ratatui::{backend::TestBackend, buffer::Buffer, style::Color, Terminal};
     use rust_decimal::prelude::*;
+    use rust_decimal_macros::dec;
+    use tokio::sync::mpsc;
+
+    use super::ui;
+    use crate::{
+        app::App,
+        arb::Cross,
+        bbo::Bbo,
+        book::Levels,
+        config::Config,
+        engine::Snapshot,
+        tape::{Side, TapeTrade},
+        AggBook,
+    };
+    use agg_ws::client::Exchange;
+
+    // Terminal sizes every fixture is drawn at, from a small laptop split to a wide screen
+    const SIZES: [(u16, u16); 3] = [(80, 24), (120, 40), (200, 50)];
+
+    const CONFIG: &str = r#"
+        default_asset = "BTC"
+        assets = [
+            { name = "BTC", size_dp = 4, venues = ["kraken", "coinbase", "hyperliquid"] },
+            { name = "ETH", size_dp = 3, venues = ["kraken", "coinbase"] },
+        ]
+    "#;
+
+    fn app(snapshot: Snapshot) -> App {
+        let config = Config::parse(CONFIG).unwrap();
+        let (commands, _) = mpsc::unbounded_channel();
+        let mut app = App::new(&config, commands).unwrap();
+        app.snapshot = Arc::new(snapshot);
+        app
+    }
+
+    fn levels(levels: &[(Decimal, Decimal)]) -> Levels {
+        levels.iter().cloned().collect()
+    }
+
+    fn trade(exchange: Exchange, price: Decimal, size: Decimal, secs: i64) -> TapeTrade {
+        let dt = Utc.timestamp_opt(1_700_000_000 + secs, 0).unwrap();
+        TapeTrade {
+            price,
+            size,
+            dt,
+            received_at: dt,
+            exchange,
+            side: Some(if secs % 3 == 0 { Side::Sell } else { Side::Buy }),
+        }
+    }
+
+    // Each venue quoting around 37000 with some prices shared across venues
+    fn venue_books() -> Vec<(Exchange, Levels, Levels)> {
+        vec![
+            (
+                Exchange::Kraken,
+                levels(&[(dec!(36999.5), dec!(0.25)), (dec!(36998), dec!(1.5))]),
+                levels(&[(dec!(37001), dec!(0.4)), (dec!(37002.5), dec!(2))]),
+            ),
+            (
+                Exchange::Gdax,
+                levels(&[
+                    (dec!(36999.5), dec!(0.75)),
+                    (dec!(36999), dec!(0.1)),
+                    (dec!(36998), dec!(0.5)),
+                ]),
+                levels(&[(dec!(37000.5), dec!(0.3)), (dec!(37002.5), dec!(1))]),
+            ),
+            (
+                Exchange::Hyperliquid,
+                levels(&[(dec!(37001.5), dec!(3))]),
+                levels(&[(dec!(37003), dec!(4.25))]),
+            ),
+        ]
+    }
+
+    fn draw(app: &mut App, width: u16, height: u16) -> Buffer {
+        let mut terminal = Terminal::new(TestBackend::new(width, height)).unwrap();
+        terminal.draw(|f| ui(f, app)).unwrap();
+        terminal.backend().buffer().clone()
+    }
+
+    // The screen as text, one line per row with trailing blanks trimmed
+    fn render(app: &mut App, width: u16, height: u16) -> String {
+        let buffer = draw(app, width, height);
+        let mut lines: Vec<String> = buffer
+            .content
+            .chunks(width as usize)
+            .map(|row| {
+                let line: String = row.iter().map(|c| c.symbol.as_str()).collect();
+                line.trim_end().to_string()
+            })
+            .collect();
+        lines.push(String::new());
+        lines.join("\n")
+    }
+
+    /// Draw the app at every size and compare with the golden buffers in `src/snapshots`. Run
+    /// with `UPDATE_GOLDEN=1` to write them after an intended layout change.
+    fn assert_golden(name: &str, app: &mut App) {
+        let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("src/snapshots");
+        for (width, height) in SIZES {
+            let actual = render(app, width, height);
+            let path = dir.join(format!("{}_{}x{}.txt", name, width, height));
+            if env::var_os("UPDATE_GOLDEN").is_some() {
+                fs::create_dir_all(&dir).unwrap();
+                fs::write(&path, &actual).unwrap();
+                continue;
+            }
+            let expected = fs::read_to_string(&path)
+                .unwrap_or_else(|_| panic!("missing {}, run with UPDATE_GOLDEN=1", path.display()));
+            let diff: Vec<String> = expected
+                .lines()
+                .zip(actual.lines())
+                .enumerate()
+                .filter(|(_, (e, a))| e != a)
+                .map(|(i, (e, a))| format!("row {}:\n  - {}\n  + {}", i, e, a))
+                .collect();
+            assert!(
+                expected == actual,
+                "{} differs:\n{}\n\n{}",
+                path.display(),
+                diff.join("\n"),
+                actual
+            );
+        }
+    }
+
+    #[test]
+    pub fn empty_book() {
+        let mut app = app(Snapshot {
+            asset: "BTC".to_string(),
+            ..Snapshot::default()
+        });
+        assert_golden("empty_book", &mut app);
+    }
+
+    #[test]
+    pub fn single_venue() {
+        let (exchange, bids, asks) = venue_books().remove(0);
+        let trades = (0..6)
+            .rev()
+            .map(|i| {
+                trade(
+                    exchange,
+                    dec!(37000) + Decimal::from(i),
+                    dec!(0.05) * Decimal::from(i + 1),
+                    i,
+                )
+            })
+            .collect();
+        let mut app = app(Snapshot {
+            asset: "BTC".to_string(),
+            trades,
+            book: AggBook::from_exchange(exchange, &bids, &asks),
+            bbo: vec![Bbo::new(
+                exchange,
+                &bids,
+                &asks,
+                Some(Duration::from_millis(400)),
+            )],
+            ..Snapshot::default()
+        });
+        app.exchange_state.get_mut("BTC").unwrap().select(Some(1));
+        assert_golden("single_venue", &mut app);
+    }
+
+    #[test]
+    pub fn aggregate_overlapping_levels() {
+        let mut book = AggBook::new();
+        let mut bbo = Vec::new();
+        for (exchange, bids, asks) in venue_books() {
+            book.merge(exchange, &bids, &asks);
+            bbo.push(Bbo::new(
+                exchange,
+                &bids,
+                &asks,
+                Some(Duration::from_millis(1_200)),
+            ));
+        }
+        let cross = Cross {
+            asset: "BTC".to_string(),
+            bid: (Exchange::Hyperliquid, dec!(37001.5)),
+            ask: (Exchange::Gdax, dec!(37000.5)),
+            size: dec!(0.3),
+            edge_bps: dec!(0.27),
+            net_bps: None,
+        };
+        let mut app = app(Snapshot {
+            asset: "BTC".to_string(),
+            book,
+            bbo,
+            crosses: vec![(cross, Duration::from_secs(3))],
+            ..Snapshot::default()
+        });
+        assert_golden("aggregate_overlapping_levels", &mut app);
+        // The crossed market banner is never squeezed out by the venue rows
+        for (width, height) in SIZES {
+            assert!(render(&mut app, width, height).contains("CROSSED 3.0s"));
+        }
+    }
+
+    #[test]
+    pub fn long_tape() {
+        let venues = [Exchange::Kraken, Exchange::Gdax, Exchange::Hyperliquid];
+        // Newest first, as published
+        let trades = (0..200)
+            .rev()
+            .map(|i| {
+                let price = dec!(36990) + Decimal::from(i % 17) * dec!(1.25);
+                let size = Decimal::from(i % 11 + 1) * dec!(0.0375);
+                trade(venues[i as usize % 3], price, size, i)
+            })
+            .collect();
+        let mut app = app(Snapshot {
+            asset: "BTC".to_string(),
+            trades,
+            ..Snapshot::default()
+        });
+        assert_golden("long_tape", &mut app);
+        // The newest trade is on the tape at every size
+        for (width, height) in SIZES {
+            assert!(render(&mut app, width, height).contains("37005.00"));
+        }
+    }
+
+    // Foreground and background of the first cell of the first occurrence of `text`, top to bottom
+    fn colours(buffer: &Buffer, text: &str) -> (Color, Color) {
+        let width = buffer.area.width as usize;
+        for (y, row) in buffer.content.chunks(width).enumerate() {
+            let line: String = row.iter().map(|c| c.symbol.as_str()).collect();
+            if let Some(x) = line.find(text) {
+                let cell = buffer.get(line[..x].chars().count() as u16, y as u16);
+                return (cell.fg, cell.bg);
+            }
+        }
+        panic!("{:?} not on screen", text);
+    }
+
+    #[test]
+    pub fn styles() {
+        let mut book = AggBook::new();
+        let mut bbo = Vec::new();
+        for (exchange, bids, asks) in venue_books() {
+            book.merge(exchange, &bids, &asks);
+            bbo.push(Bbo::new(exchange, &bids, &asks, None));
+        }
+        let mut app = app(Snapshot {
+            asset: "BTC".to_string(),
+            trades: vec![
+                trade(Exchange::Kraken, dec!(37010), dec!(1), 1),
+                trade(Exchange::Gdax, dec!(36990), dec!(2), 3),
+            ],
+            book,
+            bbo,
+            ..Snapshot::default()
+        });
+        let buffer = draw(&mut app, 120, 40);
+        // Buys are green and sells red on the tape
+        assert_eq!(colours(&buffer, "37010.00").0, Color::Green);
+        assert_eq!(colours(&buffer, "36990.00").0, Color::Red);
+        // The venues setting the best bid and offer are highlighted, the others are not
+        assert_eq!(colours(&buffer, "37001.50"), (Color::Black, Color::Cyan));
+        assert_eq!(colours(&buffer, "37000.50"), (Color::Black, Color::Red));
+        assert_eq!(colours(&buffer, "36999.50"), (Color::Cyan, Color::Black));
+    }
 
     #[test]
     pub fn rounding() {